@@ -0,0 +1,65 @@
+//! Compares drawing a board the way `post_board` used to (cloning and cropping a sprite sheet for every piece and
+//! blending it into a fresh copy of the board) with the tile renderer, uncached and cached.
+//! Run with `cargo bench`
+use chess::{Board, ChessMove, Color, Piece, ALL_SQUARES};
+use discord_chess::draw::encode_png;
+use discord_chess::pieces::PieceSet;
+use discord_chess::render::{RenderOptions, Renderer};
+use discord_chess::theme::{SQUARE_SIZE, THEMES};
+use raster::{BlendMode, Image, PositionMode};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+fn legacy_render(background: &Image, sheets: &[Image; 6], position: &Board) -> Image {
+	let mut board = background.clone();
+	for &square in ALL_SQUARES.iter() {
+		let (x, y) = (square.get_file().to_index() as i32, 7 - square.get_rank().to_index() as i32);
+		if let (Some(piece), Some(color)) = (position.piece_on(square), position.color_on(square)) {
+			let (index, white_ctr, black_ctr) = match piece {
+				Piece::Pawn => (0, 46, 116),
+				Piece::Knight => (1, 45, 120),
+				Piece::Bishop => (2, 43, 118),
+				Piece::Rook => (3, 45, 116),
+				Piece::Queen => (4, 41, 120),
+				Piece::King => (5, 42, 118),
+			};
+			let mut piece_img = sheets[index].clone();
+			let offset = if color == Color::White { white_ctr - 40 } else { black_ctr - 40 };
+			raster::editor::crop(&mut piece_img, 80, 80, PositionMode::TopLeft, offset, 0).unwrap();
+			board = raster::editor::blend(&board, &piece_img, BlendMode::Normal, 1.0, PositionMode::TopLeft, 40 + x * 80, 40 + y * 80).unwrap();
+		}
+	}
+	board
+}
+
+fn time(name: &str, mut f: impl FnMut()) -> Duration {
+	f(); // Warm up
+	let start = Instant::now();
+	for _ in 0..ITERATIONS {
+		f();
+	}
+	let each = start.elapsed() / ITERATIONS;
+	println!("{:<24}{:>10.2} ms", name, each.as_secs_f64() * 1000.0);
+	each
+}
+
+fn main() {
+	let font = raster::open("res/font.png").unwrap();
+	let renderer = Renderer::new(font.clone(), PieceSet::load_all("res/pieces"));
+	let background = THEMES[0].background(&font, false, SQUARE_SIZE);
+	let sheets = ["pawns", "knights", "bishops", "rooks", "queens", "kings"]
+		.map(|name| raster::open(&format!("res/pieces/classic/{}.png", name)).unwrap());
+	let position = Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+	let options = RenderOptions { last_move: Some(ChessMove::new(chess::Square::G8, chess::Square::F6, None)), ..RenderOptions::default() };
+
+	let legacy = time("sprite sheets", || { encode_png(&legacy_render(&background, &sheets, &position)); });
+	let tiles = time("pre-sliced tiles", || { encode_png(&renderer.render(&position, &options)); });
+	let cached = time("cached", || { renderer.render_file(&position, &options); });
+	println!(
+		"Tiles are {:.1}x as fast as sprite sheets; a cached board is {:.0}x as fast",
+		legacy.as_secs_f64() / tiles.as_secs_f64(),
+		legacy.as_secs_f64() / cached.as_secs_f64().max(1e-9)
+	);
+}
@@ -0,0 +1,90 @@
+use chess::{Board, ChessMove, Color, Piece, EMPTY, get_king_moves};
+use std::time::Duration;
+
+use crate::config::UserStats;
+use crate::game::{ChannelGame, MoveFacts, Termination};
+
+/// Win streak needed for `Achievement::WinStreak`
+pub const STREAK_LENGTH: u32 = 10;
+/// Time left on the winner's clock below which they earn `Achievement::TimeScramble`
+pub const SCRAMBLE_TIME: Duration = Duration::from_secs(60);
+
+/// A badge a player earns once, the first time they do something notable in a game
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Achievement {
+	FirstWin,
+	SmotheredMate,
+	Underpromotion,
+	TimeScramble,
+	WinStreak,
+	EnPassant,
+}
+
+impl Achievement {
+	pub const ALL: [Achievement; 6] = [
+		Achievement::FirstWin,
+		Achievement::SmotheredMate,
+		Achievement::Underpromotion,
+		Achievement::TimeScramble,
+		Achievement::WinStreak,
+		Achievement::EnPassant,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Achievement::FirstWin => "First win",
+			Achievement::SmotheredMate => "Smothered",
+			Achievement::Underpromotion => "Modest ambitions",
+			Achievement::TimeScramble => "Photo finish",
+			Achievement::WinStreak => "Unstoppable",
+			Achievement::EnPassant => "Holy hell",
+		}
+	}
+
+	pub fn description(self) -> String {
+		match self {
+			Achievement::FirstWin => "Win a game".to_owned(),
+			Achievement::SmotheredMate => "Checkmate with a knight while the king is boxed in by its own pieces".to_owned(),
+			Achievement::Underpromotion => "Promote a pawn to something other than a queen".to_owned(),
+			Achievement::TimeScramble => format!("Win a timed game with under {} seconds left", SCRAMBLE_TIME.as_secs()),
+			Achievement::WinStreak => format!("Win {} games in a row", STREAK_LENGTH),
+			Achievement::EnPassant => "Capture en passant".to_owned(),
+		}
+	}
+}
+
+/// Every achievement `color` showed in the finished game, whether or not they already hold it.
+/// `stats` are the player's statistics with this game already counted
+pub fn earned(gm: &ChannelGame, termination: Termination, color: Color, stats: &UserStats) -> Vec<Achievement> {
+	if termination == Termination::Aborted {
+		return Vec::new();
+	}
+	let won = termination.winner() == Some(color);
+	let position = gm.game.current_position();
+	let mut board = Board::default();
+	let own_moves: Vec<MoveFacts> = gm.moves().into_iter().filter_map(|mv: ChessMove| {
+		let facts = (board.side_to_move() == color).then(|| MoveFacts::of(&board, mv));
+		board = board.make_move_new(mv);
+		facts
+	}).collect();
+
+	Achievement::ALL.iter().cloned().filter(|achievement| match achievement {
+		Achievement::FirstWin => won,
+		Achievement::SmotheredMate => termination == Termination::Checkmate(color) && smothered(&position),
+		Achievement::Underpromotion => own_moves.iter().any(|m| m.promotion.is_some_and(|p| p != Piece::Queen)),
+		Achievement::TimeScramble => won && gm.clock.as_ref()
+			.is_some_and(|clock| clock.remaining(color, position.side_to_move()) < SCRAMBLE_TIME),
+		Achievement::WinStreak => stats.current_streak >= STREAK_LENGTH,
+		Achievement::EnPassant => own_moves.iter().any(|m| m.en_passant),
+	}).collect()
+}
+
+/// Whether the side to move is in check from a knight alone, with every square around their king taken by their own pieces
+fn smothered(board: &Board) -> bool {
+	let checkers = *board.checkers();
+	if checkers.popcnt() != 1 || board.piece_on(checkers.to_square()) != Some(Piece::Knight) {
+		return false;
+	}
+	let color = board.side_to_move();
+	get_king_moves(board.king_square(color)) & !board.color_combined(color) == EMPTY
+}
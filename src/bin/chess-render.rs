@@ -0,0 +1,116 @@
+//! Draws a position to a PNG with the same renderer the bot uses.
+//!
+//! ```text
+//! chess-render (--fen <FEN> | --pgn <file>) [--ply <n>] [--orientation white|black]
+//!              [--highlight <square>,...] [--arrow <e2e4[color]>,...] [--mark <d5[color]>,...]
+//!              [--theme <name>] [--pieces <name>] [--size 40|60|80|120] [--format png|palette|jpeg|webp]
+//!              [--res <dir>] [-o <file>]
+//! ```
+use chess::{Board, Color, Square};
+use discord_chess::pieces::PieceSet;
+use discord_chess::draw::ImageFormat;
+use discord_chess::render::{parse_arrow, parse_mark, RenderOptions, Renderer, SQUARE_SIZES};
+use discord_chess::san::{parse_fen, play_line};
+use std::path::Path;
+use std::process::exit;
+use std::str::FromStr;
+
+const USAGE: &str = "Usage: chess-render (--fen <FEN> | --pgn <file>) [--ply <n>] [--orientation white|black] \
+	[--highlight <square>,...] [--arrow <e2e4[color]>,...] [--mark <d5[color]>,...] [--theme <name>] [--pieces <name>] [--size 40|60|80|120] \
+	[--format png|palette|jpeg|webp] [--res <dir>] [-o <file>]";
+
+fn fail(message: &str) -> ! {
+	eprintln!("{}", message);
+	exit(1);
+}
+
+/// The SAN moves of a PGN's main line, leaving out tags, comments, variations, annotations and move numbers
+fn pgn_moves(pgn: &str) -> Vec<String> {
+	let mut movetext = String::new();
+	let (mut comment, mut variation) = (false, 0);
+	for line in pgn.lines().filter(|line| !line.trim_start().starts_with('[')) {
+		for c in line.chars() {
+			match c {
+				'{' => comment = true,
+				'}' => comment = false,
+				'(' if !comment => variation += 1,
+				')' if !comment => variation -= 1,
+				_ if comment || variation > 0 => {}
+				_ => movetext.push(c),
+			}
+		}
+		movetext.push(' ');
+	}
+	movetext.split_whitespace()
+		.filter(|token| !token.starts_with('$') && !["1-0", "0-1", "1/2-1/2", "*"].contains(token))
+		.map(|token| token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'))
+		.filter(|token| !token.is_empty())
+		.map(|token| token.trim_end_matches(['!', '?']).to_owned())
+		.collect()
+}
+
+fn main() {
+	let mut args = std::env::args().skip(1);
+	let mut fen = None;
+	let mut pgn = None;
+	let mut ply = None;
+	let mut output = None;
+	let mut res = "res".to_owned();
+	let mut options = RenderOptions::default();
+
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().unwrap_or_else(|| fail(&format!("{} needs a value\n{}", arg, USAGE)));
+		match &*arg {
+			"--fen" => fen = Some(value()),
+			"--pgn" => pgn = Some(value()),
+			"--ply" => ply = Some(value().parse::<usize>().unwrap_or_else(|_| fail("--ply takes a number of half-moves"))),
+			"--orientation" => options.orientation = match &*value() {
+				"white" => Color::White,
+				"black" => Color::Black,
+				_ => fail("--orientation is white or black"),
+			},
+			"--highlight" => options.highlights = value().split(',')
+				.map(|name| Square::from_str(name).unwrap_or_else(|_| fail(&format!("{} isn't a square", name))))
+				.collect(),
+			"--arrow" => options.arrows = value().split(',')
+				.map(|text| parse_arrow(text).unwrap_or_else(|| fail(&format!("{} isn't an arrow", text))))
+				.collect(),
+			"--mark" => options.marks = value().split(',')
+				.map(|text| parse_mark(&format!("@{}", text.trim_start_matches('@'))).unwrap_or_else(|| fail(&format!("{} isn't a square to mark", text))))
+				.collect(),
+			"--theme" => options.theme = value(),
+			"--pieces" => options.pieces = value(),
+			"--size" => options.square_size = match value().parse::<i32>() {
+				Ok(size) if SQUARE_SIZES.contains(&size) => size,
+				_ => fail("--size is 40, 60, 80 or 120"),
+			},
+			"--format" => options.format = ImageFormat::from_name(&value()).unwrap_or_else(|| fail("--format is png, palette, jpeg or webp")),
+			"--res" => res = value(),
+			"-o" | "--output" => output = Some(value()),
+			"-h" | "--help" => {
+				println!("{}", USAGE);
+				return;
+			}
+			_ => fail(&format!("Unknown argument {}\n{}", arg, USAGE)),
+		}
+	}
+
+	let position = match (fen, pgn) {
+		(Some(fen), None) => parse_fen(&fen).unwrap_or_else(|| fail("That FEN isn't a legal position")),
+		(None, Some(path)) => {
+			let text = std::fs::read_to_string(&path).unwrap_or_else(|e| fail(&format!("Can't read {}: {}", path, e)));
+			let line = pgn_moves(&text).into_iter().take(ply.unwrap_or(usize::MAX)).collect::<Vec<_>>().join(" ");
+			let (board, moves) = play_line(&Board::default(), &line).unwrap_or_else(|e| fail(&e.to_string()));
+			options.last_move = moves.last().cloned();
+			board
+		}
+		_ => fail(USAGE),
+	};
+
+	let res = Path::new(&res);
+	let font = raster::open(res.join("font.png").to_str().unwrap()).unwrap_or_else(|_| fail("Can't open font.png; is --res right?"));
+	let renderer = Renderer::new(font, PieceSet::load_all(res.join("pieces").to_str().unwrap()));
+	let output = output.unwrap_or_else(|| format!("board.{}", options.format.extension()));
+	let file = renderer.render_file(&position, &options);
+	std::fs::write(&output, &*file).unwrap_or_else(|e| fail(&format!("Can't write {}: {}", output, e)));
+}
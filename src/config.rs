@@ -1,7 +1,10 @@
 use serenity::model::id::{GuildId,UserId,ChannelId};
+use chess::Color;
 use std::collections::HashMap;
 use std::sync::RwLock;
 
+use crate::game::{Termination, DrawClaim, AutomaticDraw};
+
 pub struct Config {
 	pub guild_settings: RwLock<HashMap<GuildId, GuildConfig>>,
 	pub user_prefs: RwLock<HashMap<UserId, UserConfig>>
@@ -21,17 +24,112 @@ pub struct UserStats {
 	// Games won
 	pub won_default: u32, // Opponent resigned
 	pub won_checkmate: u32, // Checkmated opponent
+	pub won_timeout: u32, // Opponent ran out of time
+	pub won_abandoned: u32, // Opponent abandoned the game
+	pub won_adjudicated: u32, // Awarded by a moderator
 	// Games drawn
 	pub drawn_stalemate: u32, // Ended in stalemate
 	pub drawn_agreement: u32, // Drawn by agreement
-	pub drawn_declared: u32, // Drawn by declared draw
+	pub drawn_repetition: u32, // Drawn by claiming threefold repetition
+	pub drawn_fifty_move: u32, // Drawn by claiming the fifty-move rule
+	pub drawn_insufficient: u32, // Drawn automatically by insufficient material
+	pub drawn_fivefold: u32, // Drawn automatically by fivefold repetition
+	pub drawn_seventy_five_move: u32, // Drawn automatically by the seventy-five-move rule
+	pub drawn_adjudicated: u32, // Declared drawn by a moderator
 	// Games lost
 	pub lost_resigned: u32, // Resigned
 	pub lost_checkmate: u32, // Was checkmated
+	pub lost_timeout: u32, // Ran out of time
+	pub lost_abandoned: u32, // Abandoned the game
+	pub lost_adjudicated: u32, // Awarded to the opponent by a moderator
+	// Games without a result
+	pub aborted: u32,
 	// General statistics
 	pub moves_made: u32,
 	pub pieces_captured: u32,
 	pub checks_given: u32,
+	pub castles: u32,
+	pub promotions: u32,
+	pub en_passant_captures: u32,
+	// Results by color, as [won, drawn, lost]
+	pub as_white: [u32; 3],
+	pub as_black: [u32; 3],
+	// Consecutive games won
+	pub current_streak: u32,
+	pub best_streak: u32,
+	pub game_moves: u32, // Moves played in all finished games, by both sides
+}
+
+impl UserStats {
+	/// Count a finished game of `length` moves in which this user played `color`
+	pub fn record(&mut self, termination: Termination, color: Color, length: usize) {
+		if termination != Termination::Aborted {
+			let by_color = if color == Color::White { &mut self.as_white } else { &mut self.as_black };
+			match termination.winner() {
+				Some(c) if c == color => {
+					by_color[0] += 1;
+					self.current_streak += 1;
+					self.best_streak = self.best_streak.max(self.current_streak);
+				},
+				Some(_) => {
+					by_color[2] += 1;
+					self.current_streak = 0;
+				},
+				None => {
+					by_color[1] += 1;
+					self.current_streak = 0;
+				}
+			}
+			self.game_moves += length as u32;
+		}
+		match termination {
+			Termination::Checkmate(c) if c == color => self.won_checkmate += 1,
+			Termination::Checkmate(_) => self.lost_checkmate += 1,
+			Termination::Resignation(c) if c == color => self.won_default += 1,
+			Termination::Resignation(_) => self.lost_resigned += 1,
+			Termination::Timeout(c) if c == color => self.won_timeout += 1,
+			Termination::Timeout(_) => self.lost_timeout += 1,
+			Termination::Abandonment(c) if c == color => self.won_abandoned += 1,
+			Termination::Abandonment(_) => self.lost_abandoned += 1,
+			Termination::Adjudication(Some(c)) if c == color => self.won_adjudicated += 1,
+			Termination::Adjudication(Some(_)) => self.lost_adjudicated += 1,
+			Termination::Adjudication(None) => self.drawn_adjudicated += 1,
+			Termination::Stalemate => self.drawn_stalemate += 1,
+			Termination::Agreement => self.drawn_agreement += 1,
+			Termination::Claimed(DrawClaim::ThreefoldRepetition) => self.drawn_repetition += 1,
+			Termination::Claimed(DrawClaim::FiftyMoveRule) => self.drawn_fifty_move += 1,
+			Termination::Automatic(AutomaticDraw::InsufficientMaterial) => self.drawn_insufficient += 1,
+			Termination::Automatic(AutomaticDraw::FivefoldRepetition) => self.drawn_fivefold += 1,
+			Termination::Automatic(AutomaticDraw::SeventyFiveMoveRule) => self.drawn_seventy_five_move += 1,
+			Termination::Aborted => self.aborted += 1,
+		}
+	}
+
+	pub fn won(&self) -> u32 {
+		self.won_default + self.won_checkmate + self.won_timeout + self.won_abandoned + self.won_adjudicated
+	}
+
+	pub fn drawn(&self) -> u32 {
+		self.drawn_stalemate + self.drawn_agreement + self.drawn_repetition + self.drawn_fifty_move
+			+ self.drawn_insufficient + self.drawn_fivefold + self.drawn_seventy_five_move + self.drawn_adjudicated
+	}
+
+	pub fn lost(&self) -> u32 {
+		self.lost_resigned + self.lost_checkmate + self.lost_timeout + self.lost_abandoned + self.lost_adjudicated
+	}
+
+	/// Number of games finished with a result
+	pub fn games(&self) -> u32 {
+		self.won() + self.drawn() + self.lost()
+	}
+
+	/// Average number of full moves in this user's finished games
+	pub fn average_length(&self) -> Option<f64> {
+		match self.games() {
+			0 => None,
+			games => Some(self.game_moves as f64 / games as f64 / 2.0)
+		}
+	}
 }
 
 impl Config {
@@ -42,12 +140,34 @@ impl Config {
 	pub fn lazy_user(&self, id: UserId) {
 		self.user_prefs.write().unwrap().entry(id).or_insert_with(UserConfig::new);
 	}
+
+	/// Read one of a guild's settings, or an empty string if it isn't set
+	pub fn guild_setting(&self, id: GuildId, key: &str) -> String {
+		self.lazy_guild(id);
+		self.guild_settings.read().unwrap().get(&id).unwrap().settings.get(key).cloned().unwrap_or_default()
+	}
+
+	/// Read one of a user's preferences, or an empty string if it isn't set
+	pub fn user_setting(&self, id: UserId, key: &str) -> String {
+		self.lazy_user(id);
+		self.user_prefs.read().unwrap().get(&id).unwrap().settings.get(key).cloned().unwrap_or_default()
+	}
+
+	/// Read one of a user's preferences as a flag, treating anything but "true" as false
+	pub fn user_flag(&self, id: UserId, key: &str) -> bool {
+		self.lazy_user(id);
+		self.user_prefs.read().unwrap().get(&id).unwrap().settings.get(key).and_then(|v| v.parse::<bool>().ok()).unwrap_or(false)
+	}
 }
 
 impl GuildConfig {
 	fn new() -> GuildConfig {
 		let mut gc = GuildConfig { settings: HashMap::new(), permissions: HashMap::new() };
 		gc.settings.insert("deleteOld".to_string(), "onNext".to_string());
+		gc.settings.insert("ratingSystem".to_string(), "elo".to_string()); // Or "glicko2"
+		gc.settings.insert("theme".to_string(), "green".to_string()); // Board colours, unless a user picks their own
+		gc.settings.insert("boardSize".to_string(), "80".to_string()); // Pixels per square: 40, 60, 80 or 120
+		gc.settings.insert("imageFormat".to_string(), "png".to_string()); // Or "palette", "jpeg" or "webp"
 		gc.permissions.insert("allow".to_string(), true);
 		gc
 	}
@@ -116,8 +236,11 @@ impl UserConfig {
 		let mut cfg = UserConfig {
 			settings: HashMap::new()
 		};
-		cfg.settings.insert("flipIfBlack".to_owned(), "true".to_owned());
+		cfg.settings.insert("orientation".to_owned(), "toMove".to_owned()); // Or "white" or "own"
 		cfg.settings.insert("pingMyTurn".to_owned(), "true".to_owned());
+		cfg.settings.insert("confirmResign".to_owned(), "true".to_owned());
+		cfg.settings.insert("confirmDraw".to_owned(), "false".to_owned());
+		cfg.settings.insert("sidePanel".to_owned(), "false".to_owned()); // Captures and moves beside the board
 		cfg
 	}
 }
\ No newline at end of file
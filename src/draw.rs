@@ -0,0 +1,354 @@
+use raster::{Color, Image};
+use std::collections::HashMap;
+
+/// Size of one cell of the glyph sheet in `res/font.png`, which holds ASCII 32 to 126 in order
+pub const GLYPH_WIDTH: i32 = 17;
+pub const GLYPH_HEIGHT: i32 = 34;
+
+/// Blend `color` over the pixel at (`x`, `y`), scaling its alpha by `coverage`
+pub fn blend_pixel(img: &mut Image, x: i32, y: i32, color: &Color, coverage: f32) {
+	if x < 0 || y < 0 || x >= img.width || y >= img.height {
+		return;
+	}
+	let alpha = coverage * color.a as f32 / 255.0;
+	if alpha <= 0.0 {
+		return;
+	}
+	let idx = ((y * img.width + x) * 4) as usize;
+	let px = &mut img.bytes[idx..idx + 4];
+	let dst_alpha = px[3] as f32 / 255.0;
+	let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+	for (channel, &src) in px.iter_mut().zip(&[color.r, color.g, color.b]) {
+		let mixed = (src as f32 * alpha + *channel as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+		*channel = mixed.round() as u8;
+	}
+	px[3] = (out_alpha * 255.0).round() as u8;
+}
+
+pub fn fill_rect(img: &mut Image, x: i32, y: i32, w: i32, h: i32, color: &Color) {
+	for py in y.max(0)..(y + h).min(img.height) {
+		for px in x.max(0)..(x + w).min(img.width) {
+			blend_pixel(img, px, py, color, 1.0);
+		}
+	}
+}
+
+/// Draw `src` over `img` with its top left corner at (`x`, `y`), blending by `src`'s alpha
+pub fn blit(img: &mut Image, src: &Image, x: i32, y: i32) {
+	for sy in 0..src.height {
+		for sx in 0..src.width {
+			let idx = ((sy * src.width + sx) * 4) as usize;
+			let px = &src.bytes[idx..idx + 4];
+			match px[3] {
+				0 => {}
+				255 if (0..img.width).contains(&(x + sx)) && (0..img.height).contains(&(y + sy)) => {
+					let dst = (((y + sy) * img.width + x + sx) * 4) as usize;
+					img.bytes[dst..dst + 4].copy_from_slice(px);
+				}
+				alpha => blend_pixel(img, x + sx, y + sy, &Color::rgba(px[0], px[1], px[2], alpha), 1.0),
+			}
+		}
+	}
+}
+
+/// A copy of `src` scaled to `width` by `height`, each pixel averaging the part of `src` it covers
+pub fn resize(src: &Image, width: i32, height: i32) -> Image {
+	// For each output column or row, the input ones it overlaps and by how much
+	fn spans(from: i32, to: i32) -> Vec<Vec<(usize, f32)>> {
+		let scale = from as f32 / to as f32;
+		(0..to).map(|i| {
+			let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+			let span: Vec<(usize, f32)> = (start.floor() as i32..(end.ceil() as i32).min(from))
+				.map(|j| (j as usize, end.min(j as f32 + 1.0) - start.max(j as f32)))
+				.filter(|&(_, weight)| weight > 0.0)
+				.collect();
+			let total: f32 = span.iter().map(|&(_, weight)| weight).sum();
+			span.into_iter().map(|(j, weight)| (j, weight / total)).collect()
+		}).collect()
+	}
+
+	// Premultiplied, so transparent pixels don't darken their neighbours
+	let pixels: Vec<[f32; 4]> = src.bytes.chunks(4).map(|px| {
+		let alpha = px[3] as f32 / 255.0;
+		[px[0] as f32 * alpha, px[1] as f32 * alpha, px[2] as f32 * alpha, px[3] as f32]
+	}).collect();
+	let (columns, rows) = (spans(src.width, width), spans(src.height, height));
+
+	let mut across = vec![[0.0f32; 4]; (width * src.height) as usize];
+	for y in 0..src.height as usize {
+		for (x, span) in columns.iter().enumerate() {
+			let out = &mut across[y * width as usize + x];
+			for &(sx, weight) in span {
+				for c in 0..4 {
+					out[c] += pixels[y * src.width as usize + sx][c] * weight;
+				}
+			}
+		}
+	}
+
+	let mut img = Image::blank(width, height);
+	for (y, span) in rows.iter().enumerate() {
+		for x in 0..width as usize {
+			let mut px = [0.0f32; 4];
+			for &(sy, weight) in span {
+				for c in 0..4 {
+					px[c] += across[sy * width as usize + x][c] * weight;
+				}
+			}
+			let idx = (y * width as usize + x) * 4;
+			let alpha = px[3] / 255.0;
+			for (channel, &value) in img.bytes[idx..idx + 3].iter_mut().zip(&px) {
+				*channel = if alpha > 0.0 { (value / alpha).round().min(255.0) as u8 } else { 0 };
+			}
+			img.bytes[idx + 3] = px[3].round() as u8;
+		}
+	}
+	img
+}
+
+/// Draw an arrow from (`x0`, `y0`) to its tip at (`x1`, `y1`) with antialiased edges
+pub fn draw_arrow(img: &mut Image, (x0, y0): (f32, f32), (x1, y1): (f32, f32), width: f32, head_length: f32, color: &Color) {
+	let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+	if length < 1.0 {
+		return;
+	}
+	let (dx, dy) = ((x1 - x0) / length, (y1 - y0) / length);
+	let head_length = head_length.min(length);
+	let head_width = width * 1.5;
+	let neck = length - head_length;
+
+	let margin = head_width + 1.0;
+	let (left, right) = ((x0.min(x1) - margin).floor() as i32, (x0.max(x1) + margin).ceil() as i32);
+	let (top, bottom) = ((y0.min(y1) - margin).floor() as i32, (y0.max(y1) + margin).ceil() as i32);
+	for py in top..=bottom {
+		for px in left..=right {
+			// Position of the pixel's centre along the arrow and across it
+			let (rx, ry) = (px as f32 + 0.5 - x0, py as f32 + 0.5 - y0);
+			let along = rx * dx + ry * dy;
+			let across = (ry * dx - rx * dy).abs();
+
+			let shaft = if along < neck { (width / 2.0 + 0.5 - across).min(along + 0.5) } else { 0.0 };
+			// Inside the head, the half-width shrinks linearly to nothing at the tip
+			let head = if along >= neck - 0.5 {
+				let half = head_width * (length - along) / head_length;
+				// Distance to the slanted edge, measured perpendicular to it
+				let slant = (half - across) * head_length / (head_length.powi(2) + head_width.powi(2)).sqrt();
+				(slant + 0.5).min(along - neck + 0.5)
+			} else {
+				0.0
+			};
+			let coverage = shaft.max(head).clamp(0.0, 1.0);
+			if coverage > 0.0 {
+				blend_pixel(img, px, py, color, coverage);
+			}
+		}
+	}
+}
+
+/// Draw `text` with its top left corner at (`x`, `y`) and glyphs `height` pixels tall.
+/// Characters outside printable ASCII are drawn as `?`
+pub fn draw_text(img: &mut Image, font: &Image, text: &str, x: i32, y: i32, height: i32, color: &Color) {
+	let scale = GLYPH_HEIGHT as f32 / height as f32;
+	let width = GLYPH_WIDTH * height / GLYPH_HEIGHT;
+
+	for (i, c) in text.chars().enumerate() {
+		let index = if (' '..='~').contains(&c) { c as i32 - 32 } else { '?' as i32 - 32 };
+		let left = x + i as i32 * width;
+		for dy in 0..height {
+			for dx in 0..width {
+				// Average the glyph sheet's alpha over the area this pixel covers
+				let sx0 = (index * GLYPH_WIDTH) as f32 + dx as f32 * scale;
+				let sy0 = dy as f32 * scale;
+				let (mut total, mut count) = (0u32, 0u32);
+				for sy in sy0 as i32..((sy0 + scale).ceil() as i32).min(GLYPH_HEIGHT) {
+					for sx in sx0 as i32..((sx0 + scale).ceil() as i32).min((index + 1) * GLYPH_WIDTH) {
+						total += font.bytes[((sy * font.width + sx) * 4 + 3) as usize] as u32;
+						count += 1;
+					}
+				}
+				if count > 0 && total > 0 {
+					blend_pixel(img, left + dx, y + dy, color, total as f32 / (count * 255) as f32);
+				}
+			}
+		}
+	}
+}
+
+/// File formats images can be sent in
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ImageFormat {
+	Png,
+	PalettePng, // At most 256 colours, for smaller files
+	Jpeg,
+	Webp, // Lossless
+}
+
+impl ImageFormat {
+	pub fn from_name(name: &str) -> Option<ImageFormat> {
+		match &*name.to_ascii_lowercase() {
+			"png" => Some(ImageFormat::Png),
+			"palette" => Some(ImageFormat::PalettePng),
+			"jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+			"webp" => Some(ImageFormat::Webp),
+			_ => None,
+		}
+	}
+
+	pub fn extension(self) -> &'static str {
+		match self {
+			ImageFormat::Png | ImageFormat::PalettePng => "png",
+			ImageFormat::Jpeg => "jpg",
+			ImageFormat::Webp => "webp",
+		}
+	}
+
+	pub fn encode(self, img: &Image) -> Vec<u8> {
+		match self {
+			ImageFormat::Png => encode_png(img),
+			ImageFormat::PalettePng => encode_palette_png(img),
+			ImageFormat::Jpeg => encode_jpeg(img),
+			ImageFormat::Webp => encode_webp(img),
+		}
+	}
+}
+
+pub fn encode_png(img: &Image) -> Vec<u8> {
+	use png::{Encoder, Compression, ColorType, BitDepth};
+
+	let mut bytes = Vec::<u8>::new();
+	let mut encoder = Encoder::new(&mut bytes, img.width as u32, img.height as u32);
+	encoder.set_color(ColorType::RGBA);
+	encoder.set_depth(BitDepth::Eight);
+	encoder.set_compression(Compression::Fast);
+
+	let mut writer = encoder.write_header().unwrap();
+	writer.write_image_data(&img.bytes).unwrap();
+	std::mem::drop(writer);
+
+	bytes
+}
+
+/// An indexed PNG, using the image's own colours if there are few enough and the closest 256 otherwise
+pub fn encode_palette_png(img: &Image) -> Vec<u8> {
+	use png::{Encoder, Compression, ColorType, BitDepth};
+
+	let mut colors: HashMap<[u8; 4], u8> = HashMap::new();
+	for px in img.bytes.chunks(4) {
+		if colors.len() > 256 {
+			break;
+		}
+		let next = colors.len() as u8;
+		colors.entry([px[0], px[1], px[2], px[3]]).or_insert(next);
+	}
+	let (palette, indices): (Vec<[u8; 4]>, Vec<u8>) = if colors.len() <= 256 {
+		let mut palette = vec![[0; 4]; colors.len()];
+		for (&color, &index) in &colors {
+			palette[index as usize] = color;
+		}
+		(palette, img.bytes.chunks(4).map(|px| colors[&[px[0], px[1], px[2], px[3]]]).collect())
+	} else {
+		let quantizer = color_quant::NeuQuant::new(10, 256, &img.bytes);
+		let palette = quantizer.color_map_rgba().chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect();
+		(palette, img.bytes.chunks(4).map(|px| quantizer.index_of(px) as u8).collect())
+	};
+
+	let mut bytes = Vec::<u8>::new();
+	let mut encoder = Encoder::new(&mut bytes, img.width as u32, img.height as u32);
+	encoder.set_color(ColorType::Indexed);
+	encoder.set_depth(BitDepth::Eight);
+	encoder.set_compression(Compression::Default);
+	encoder.set_palette(palette.iter().flat_map(|c| c[..3].to_vec()).collect());
+	if palette.iter().any(|c| c[3] < 255) {
+		encoder.set_trns(palette.iter().map(|c| c[3]).collect());
+	}
+
+	let mut writer = encoder.write_header().unwrap();
+	writer.write_image_data(&indices).unwrap();
+	std::mem::drop(writer);
+
+	bytes
+}
+
+/// JPEG has no transparency, so transparent pixels come out black
+pub fn encode_jpeg(img: &Image) -> Vec<u8> {
+	let mut bytes = Vec::<u8>::new();
+	jpeg_encoder::Encoder::new(&mut bytes, 90)
+		.encode(&img.bytes, img.width as u16, img.height as u16, jpeg_encoder::ColorType::Rgba)
+		.unwrap();
+	bytes
+}
+
+pub fn encode_webp(img: &Image) -> Vec<u8> {
+	let mut bytes = Vec::<u8>::new();
+	image_webp::WebPEncoder::new(&mut bytes)
+		.encode(&img.bytes, img.width as u32, img.height as u32, image_webp::ColorType::Rgba8)
+		.unwrap();
+	bytes
+}
+
+/// Writes an animated GIF a frame at a time. Every frame shares one palette, and only the part of each frame
+/// that differs from the one before is stored
+pub struct GifWriter {
+	encoder: gif::Encoder<Vec<u8>>,
+	quantizer: color_quant::NeuQuant,
+	previous: Option<Image>,
+}
+
+impl GifWriter {
+	/// Start a looping animation of `width` by `height` frames, choosing the palette from the colours of `samples`
+	pub fn new(width: i32, height: i32, samples: &[&Image]) -> GifWriter {
+		let pixels: Vec<u8> = samples.iter().flat_map(|img| img.bytes.iter().cloned()).collect();
+		let quantizer = color_quant::NeuQuant::new(10, 256, &pixels);
+		let mut encoder = gif::Encoder::new(Vec::new(), width as u16, height as u16, &quantizer.color_map_rgb()).unwrap();
+		encoder.set_repeat(gif::Repeat::Infinite).unwrap();
+		GifWriter { encoder, quantizer, previous: None }
+	}
+
+	/// Add `img`, shown for `delay` hundredths of a second
+	pub fn add_frame(&mut self, img: Image, delay: u16) {
+		let width = img.width as usize;
+		let row_differs = |y: usize, prev: &Image| img.bytes[y * width * 4..(y + 1) * width * 4] != prev.bytes[y * width * 4..(y + 1) * width * 4];
+		let column_differs = |x: usize, prev: &Image| (0..img.height as usize).any(|y| {
+			let idx = (y * width + x) * 4;
+			img.bytes[idx..idx + 4] != prev.bytes[idx..idx + 4]
+		});
+
+		// The smallest rectangle holding every changed pixel; GIF frames can't be empty, so at least one pixel
+		let (left, top, right, bottom) = match &self.previous {
+			None => (0, 0, width, img.height as usize),
+			Some(prev) => match (0..img.height as usize).find(|&y| row_differs(y, prev)) {
+				None => (0, 0, 1, 1),
+				Some(top) => {
+					let bottom = (top..img.height as usize).rev().find(|&y| row_differs(y, prev)).unwrap() + 1;
+					let left = (0..width).find(|&x| column_differs(x, prev)).unwrap();
+					let right = (left..width).rev().find(|&x| column_differs(x, prev)).unwrap() + 1;
+					(left, top, right, bottom)
+				}
+			},
+		};
+
+		let mut indices = Vec::with_capacity((right - left) * (bottom - top));
+		for y in top..bottom {
+			for x in left..right {
+				let idx = (y * width + x) * 4;
+				indices.push(self.quantizer.index_of(&img.bytes[idx..idx + 4]) as u8);
+			}
+		}
+		let frame = gif::Frame {
+			left: left as u16,
+			top: top as u16,
+			width: (right - left) as u16,
+			height: (bottom - top) as u16,
+			delay,
+			dispose: gif::DisposalMethod::Keep,
+			buffer: indices.into(),
+			..gif::Frame::default()
+		};
+		self.encoder.write_frame(&frame).unwrap();
+		self.previous = Some(img);
+	}
+
+	pub fn finish(self) -> Vec<u8> {
+		self.encoder.into_inner().unwrap()
+	}
+}
@@ -1,17 +1,31 @@
 use chess::*;
-use serenity::model::id::{UserId, MessageId};
+use serenity::model::id::{GuildId, UserId, MessageId};
 use std::collections::VecDeque;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use discord_chess::san::FromSan;
+
+use crate::achievement::Achievement;
 
 pub struct ChannelGame {
 	pub game: Game,
 	pub state: ChannelGameState,
+	pub guild: GuildId,
+	pub rated: bool,
+	pub rating_changes: Option<[f64; 2]>, // Indexed by `Color::to_index`, once a rated game has finished
 	pub old_boards: Mutex<VecDeque<MessageId>>,
 	pub white: UserId,
 	pub black: UserId,
 	pub initiator: Color,
 	pub draw_offer: Option<Color>,
 	pub last_move: Option<ChessMove>,
+	pub clock: Option<Clock>,
+	pub termination: Option<Termination>,
+	pub pending_confirmations: [Option<PendingConfirmation>; 2], // Indexed by `Color::to_index`
+	pub premoves: Vec<Premove>, // Queued by the player waiting for their opponent
+	pub new_achievements: Vec<(UserId, Achievement)>, // Earned in this game, to announce with the final board
+	pub record: Option<usize>, // Index of the game in the history, once it has finished
 }
 
 impl ChannelGame {
@@ -19,12 +33,21 @@ impl ChannelGame {
 		ChannelGame {
 			game: Game::new(),
 			state: ChannelGameState::Inactive,
+			guild: GuildId::default(),
+			rated: true,
+			rating_changes: None,
 			old_boards: Mutex::new(VecDeque::new()),
 			white: UserId::default(),
 			black: UserId::default(),
 			initiator: Color::White,
 			draw_offer: None,
 			last_move: None,
+			clock: None,
+			termination: None,
+			pending_confirmations: [None, None],
+			premoves: Vec::new(),
+			new_achievements: Vec::new(),
+			record: None,
 		}
 	}
 
@@ -43,6 +66,161 @@ impl ChannelGame {
 			Color::Black => self.white
 		}
 	}
+
+	#[inline]
+	pub fn player(&self, color: Color) -> UserId {
+		match color {
+			Color::White => self.white,
+			Color::Black => self.black
+		}
+	}
+
+	/// The colour `user` is playing in this game, if they are playing
+	pub fn player_color(&self, user: UserId) -> Option<Color> {
+		if user == self.white {
+			Some(Color::White)
+		} else if user == self.black {
+			Some(Color::Black)
+		} else {
+			None
+		}
+	}
+
+	/// Consume `color`'s pending confirmation of `action`, returning whether it was still live
+	pub fn take_confirmation(&mut self, color: Color, action: Confirmable) -> bool {
+		let slot = &mut self.pending_confirmations[color.to_index()];
+		match slot.take() {
+			Some(pending) if pending.action == action => pending.requested.elapsed() < CONFIRM_TIMEOUT,
+			other => {
+				*slot = other;
+				false
+			}
+		}
+	}
+
+	/// Pick the queued move which answers the opponent playing `mv` from `before`, clearing the queue.
+	/// A conditional move for `mv` wins over an unconditional premove
+	pub fn take_premove(&mut self, before: &Board, mv: ChessMove) -> Option<Premove> {
+		let mut premoves = std::mem::take(&mut self.premoves);
+		let index = premoves.iter()
+			.position(|p| p.condition.as_ref().is_some_and(|c| <ChessMove as FromSan>::from_san(before, c).ok() == Some(mv)))
+			.or_else(|| premoves.iter().position(|p| p.condition.is_none()))?;
+		Some(premoves.swap_remove(index))
+	}
+
+	/// Every position reached in this game, starting with the initial position
+	pub fn positions(&self) -> Vec<Board> {
+		let mut positions = vec![Board::default()];
+		for action in self.game.actions() {
+			if let Action::MakeMove(mv) = *action {
+				let next = positions.last().unwrap().make_move_new(mv);
+				positions.push(next);
+			}
+		}
+		positions
+	}
+
+	/// Number of half-moves since the last capture or pawn move
+	pub fn halfmove_clock(&self) -> u32 {
+		let mut board = Board::default();
+		let mut clock = 0;
+		for action in self.game.actions() {
+			if let Action::MakeMove(mv) = *action {
+				if board.piece_on(mv.get_source()) == Some(Piece::Pawn) || board.piece_on(mv.get_dest()).is_some() {
+					clock = 0;
+				} else {
+					clock += 1;
+				}
+				board = board.make_move_new(mv);
+			}
+		}
+		clock
+	}
+
+	/// Number of times the current position has occurred in this game
+	pub fn repetitions(&self) -> usize {
+		let positions = self.positions();
+		let current = positions.last().unwrap().get_hash();
+		positions.iter().filter(|b| b.get_hash() == current).count()
+	}
+
+	/// Every move made in this game, in order
+	pub fn moves(&self) -> Vec<ChessMove> {
+		self.game.actions().iter().filter_map(|action| match *action {
+			Action::MakeMove(mv) => Some(mv),
+			_ => None,
+		}).collect()
+	}
+
+	/// How the game has ended on the board after the last move, if it has
+	pub fn detect_termination(&self) -> Option<Termination> {
+		let position = self.game.current_position();
+		match position.status() {
+			BoardStatus::Checkmate => Some(Termination::Checkmate(!position.side_to_move())),
+			BoardStatus::Stalemate => Some(Termination::Stalemate),
+			BoardStatus::Ongoing => self.detect_automatic_draw().map(Termination::Automatic),
+		}
+	}
+
+	/// The rule by which the game is drawn without either player claiming it, if any
+	pub fn detect_automatic_draw(&self) -> Option<AutomaticDraw> {
+		if self.game.result().is_some() {
+			None
+		} else if insufficient_material(&self.game.current_position()) {
+			Some(AutomaticDraw::InsufficientMaterial)
+		} else if self.repetitions() >= 5 {
+			Some(AutomaticDraw::FivefoldRepetition)
+		} else if self.halfmove_clock() >= 150 {
+			Some(AutomaticDraw::SeventyFiveMoveRule)
+		} else {
+			None
+		}
+	}
+
+	/// The grounds on which the side to move could claim a draw, if any
+	pub fn claimable_draw(&self) -> Option<DrawClaim> {
+		if !self.game.can_declare_draw() {
+			None
+		} else if self.halfmove_clock() >= 100 {
+			Some(DrawClaim::FiftyMoveRule)
+		} else {
+			Some(DrawClaim::ThreefoldRepetition)
+		}
+	}
+}
+
+pub fn color_name(color: Color) -> &'static str {
+	match color {
+		Color::White => "White",
+		Color::Black => "Black"
+	}
+}
+
+/// Which side a user wants at the bottom of the board, from their `orientation` preference
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+	White,
+	Own, // Their colour in games they play, White in others
+	ToMove,
+}
+
+impl Orientation {
+	pub fn from_setting(setting: &str) -> Orientation {
+		match setting {
+			"white" => Orientation::White,
+			"own" => Orientation::Own,
+			_ => Orientation::ToMove,
+		}
+	}
+
+	/// The side to show at the bottom for a user playing `own`, if they're playing, with `to_move` to play
+	pub fn side(self, own: Option<Color>, to_move: Color) -> Color {
+		match self {
+			Orientation::White => Color::White,
+			Orientation::Own => own.unwrap_or(Color::White),
+			Orientation::ToMove => to_move,
+		}
+	}
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -52,345 +230,390 @@ pub enum ChannelGameState {
 	Running,
 }
 
-pub enum MoveError {
-	Illegal,
-	IllFormed,
-	Ambiguous,
+/// How long a player has to confirm resigning or accepting a draw
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Confirmable {
+	Resign,
+	AcceptDraw,
 }
 
-pub trait FromSan {
-	fn from_san(board: &Board, move_text: &str) -> Result<ChessMove, MoveError>;
+pub struct PendingConfirmation {
+	pub action: Confirmable,
+	pub prompt: MessageId, // Reacting to this message confirms the action
+	pub requested: Instant,
 }
 
-impl FromSan for ChessMove {
-	//* Ripped from https://github.com/jordanbray/chess/blob/master/src/chess_move.rs
-	//* Not my algorithm
-
-	/// Convert a SAN (Standard Algebraic Notation) move into a `ChessMove`
-	///
-	/// ```
-	/// use chess::{Board, ChessMove, Square};
-	///
-	/// let board = Board::default();
-	/// assert_eq!(
-	///     ChessMove::from_san(&board, "e4").expect("e4 is valid in the initial position"),
-	///     ChessMove::new(Square::E2, Square::E4, None)
-	/// );
-	/// ```
-	fn from_san(board: &Board, move_text: &str) -> Result<ChessMove, MoveError> {
-		// Castles first...
-		if move_text == "O-O" || move_text == "O-O-O" {
-			let rank = board.side_to_move().to_my_backrank();
-			let source_file = File::E;
-			let dest_file = if move_text == "O-O" { File::G } else { File::C };
-
-			let m = ChessMove::new(
-				Square::make_square(rank, source_file),
-				Square::make_square(rank, dest_file),
-				None,
-			);
-			if MoveGen::new_legal(&board).any(|l| l == m) {
-				return Ok(m);
-			} else {
-				return Err(MoveError::Illegal);
-			}
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DrawClaim {
+	ThreefoldRepetition,
+	FiftyMoveRule,
+}
+
+impl DrawClaim {
+	pub fn description(self) -> &'static str {
+		match self {
+			DrawClaim::ThreefoldRepetition => "threefold repetition",
+			DrawClaim::FiftyMoveRule => "the fifty-move rule",
 		}
+	}
+}
 
-		// forms of SAN moves
-		// a4 (Pawn moves to a4)
-		// exd4 (Pawn on e file takes on d4)
-		// xd4 (Illegal, source file must be specified)
-		// 1xd4 (Illegal, source file (not rank) must be specified)
-		// Nc3 (Knight (or any piece) on *some square* to c3
-		// Nb1c3 (Knight (or any piece) on b1 to c3
-		// Nbc3 (Knight on b file to c3)
-		// N1c3 (Knight on first rank to c3)
-		// Nb1xc3 (Knight on b1 takes on c3)
-		// Nbxc3 (Knight on b file takes on c3)
-		// N1xc3 (Knight on first rank takes on c3)
-		// Nc3+ (Knight moves to c3 with check)
-		// Nc3# (Knight moves to c3 with checkmate)
-
-		// Because I'm dumb, I'm wondering if a hash table of all possible moves would be stupid.
-		// There are only 186624 possible moves in SAN notation.
-		//
-		// Would this even be faster?  Somehow I doubt it because caching, but maybe, I dunno...
-		// This could take the form of a:
-		// struct CheckOrCheckmate {
-		//      Neither,
-		//      Check,
-		//      CheckMate,
-		// }
-		// struct FromSan {
-		//      piece: Piece,
-		//      source: Vec<Square>, // possible source squares
-		//      // OR
-		//      source_rank: Option<Rank>,
-		//      source_file: Option<File>,
-		//      dest: Square,
-		//      takes: bool,
-		//      check: CheckOrCheckmate
-		// }
-		//
-		// This could be kept internally as well, and never tell the user about such an abomination
-		//
-		// I estimate this table would take around 2 MiB, but I had to approximate some things.  It
-		// may be less
-
-		// This can be described with the following format
-		// [Optional Piece Specifier] ("" | "N" | "B" | "R" | "Q" | "K")
-		// [Optional Source Specifier] ( "" | "a-h" | "1-8" | ("a-h" + "1-8"))
-		// [Optional Takes Specifier] ("" | "x")
-		// [Full Destination Square] ("a-h" + "0-8")
-		// [Optional Promotion Specifier] ("" | "=N" | "=B" | "=R" | "=Q")
-		// [Optional Check(mate) Specifier] ("" | "+" | "#")
-		// [Optional En Passant Specifier] ("" | " e.p.")
-
-		let mut cur_index: usize = 0;
-		let moving_piece = match move_text
-			.get(cur_index..(cur_index + 1))
-			.ok_or(MoveError::IllFormed)?
-		{
-			"N" => {
-				cur_index += 1;
-				Piece::Knight
-			}
-			"B" => {
-				cur_index += 1;
-				Piece::Bishop
-			}
-			"Q" => {
-				cur_index += 1;
-				Piece::Queen
-			}
-			"R" => {
-				cur_index += 1;
-				Piece::Rook
-			}
-			"K" => {
-				cur_index += 1;
-				Piece::King
-			}
-			_ => Piece::Pawn,
-		};
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AutomaticDraw {
+	InsufficientMaterial,
+	FivefoldRepetition,
+	SeventyFiveMoveRule,
+}
 
-		let mut source_file = match move_text
-			.get(cur_index..(cur_index + 1))
-			.ok_or(MoveError::IllFormed)?
-		{
-			"a" => {
-				cur_index += 1;
-				Some(File::A)
-			}
-			"b" => {
-				cur_index += 1;
-				Some(File::B)
-			}
-			"c" => {
-				cur_index += 1;
-				Some(File::C)
-			}
-			"d" => {
-				cur_index += 1;
-				Some(File::D)
-			}
-			"e" => {
-				cur_index += 1;
-				Some(File::E)
-			}
-			"f" => {
-				cur_index += 1;
-				Some(File::F)
-			}
-			"g" => {
-				cur_index += 1;
-				Some(File::G)
-			}
-			"h" => {
-				cur_index += 1;
-				Some(File::H)
-			}
-			_ => None,
-		};
+impl AutomaticDraw {
+	pub fn description(self) -> &'static str {
+		match self {
+			AutomaticDraw::InsufficientMaterial => "insufficient material",
+			AutomaticDraw::FivefoldRepetition => "fivefold repetition",
+			AutomaticDraw::SeventyFiveMoveRule => "the seventy-five-move rule",
+		}
+	}
+}
 
-		let mut source_rank = match move_text
-			.get(cur_index..(cur_index + 1))
-			.ok_or(MoveError::IllFormed)?
-		{
-			"1" => {
-				cur_index += 1;
-				Some(Rank::First)
-			}
-			"2" => {
-				cur_index += 1;
-				Some(Rank::Second)
-			}
-			"3" => {
-				cur_index += 1;
-				Some(Rank::Third)
-			}
-			"4" => {
-				cur_index += 1;
-				Some(Rank::Fourth)
-			}
-			"5" => {
-				cur_index += 1;
-				Some(Rank::Fifth)
-			}
-			"6" => {
-				cur_index += 1;
-				Some(Rank::Sixth)
-			}
-			"7" => {
-				cur_index += 1;
-				Some(Rank::Seventh)
-			}
-			"8" => {
-				cur_index += 1;
-				Some(Rank::Eighth)
-			}
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)] // Not every way a game can end is reachable from a command, but records and PGNs can hold them all
+pub enum Termination {
+	// Decisive, holding the winner
+	Checkmate(Color),
+	Resignation(Color),
+	Timeout(Color),
+	Abandonment(Color),
+	// Drawn
+	Stalemate,
+	Agreement,
+	Claimed(DrawClaim),
+	Automatic(AutomaticDraw),
+	// Decided by a moderator; `None` if drawn
+	Adjudication(Option<Color>),
+	// Called off without a result
+	Aborted,
+}
+
+impl Termination {
+	pub fn winner(self) -> Option<Color> {
+		match self {
+			Termination::Checkmate(c) | Termination::Resignation(c) | Termination::Timeout(c) | Termination::Abandonment(c) => Some(c),
+			Termination::Adjudication(c) => c,
 			_ => None,
-		};
+		}
+	}
 
-		let takes = if let Some(s) = move_text.get(cur_index..(cur_index + 1)) {
-			match s {
-				"x" => {
-					cur_index += 1;
-					true
-				}
-				_ => false,
-			}
-		} else {
-			false
-		};
+	pub fn description(self) -> String {
+		match self {
+			Termination::Checkmate(c) => format!("{} wins by checkmate", color_name(c)),
+			Termination::Resignation(c) => format!("{} has resigned", color_name(!c)),
+			Termination::Timeout(c) => format!("{} wins on time", color_name(c)),
+			Termination::Abandonment(c) => format!("{} wins; {} abandoned the game", color_name(c), color_name(!c)),
+			Termination::Stalemate => "Drawn by stalemate".to_owned(),
+			Termination::Agreement => "Drawn by agreement".to_owned(),
+			Termination::Claimed(claim) => format!("Drawn by {}", claim.description()),
+			Termination::Automatic(draw) => format!("Drawn by {}", draw.description()),
+			Termination::Adjudication(Some(c)) => format!("{} wins by adjudication", color_name(c)),
+			Termination::Adjudication(None) => "Drawn by adjudication".to_owned(),
+			Termination::Aborted => "The game was aborted".to_owned(),
+		}
+	}
 
-		let dest = if let Some(s) = move_text.get(cur_index..(cur_index + 2)) {
-			if let Some(q) = Square::from_string(String::from(s)) {
-				cur_index += 2;
-				q
-			} else {
-				let sq = Square::make_square(
-					source_rank.ok_or(MoveError::IllFormed)?,
-					source_file.ok_or(MoveError::IllFormed)?,
-				);
-				source_rank = None;
-				source_file = None;
-				sq
-			}
-		} else {
-			let sq = Square::make_square(
-				source_rank.ok_or(MoveError::IllFormed)?,
-				source_file.ok_or(MoveError::IllFormed)?,
-			);
-			source_rank = None;
-			source_file = None;
-			sq
-		};
+	/// The score White earned from this game, or `None` if it was aborted
+	pub fn white_score(self) -> Option<f64> {
+		match self.winner() {
+			Some(Color::White) => Some(1.0),
+			Some(Color::Black) => Some(0.0),
+			None if self == Termination::Aborted => None,
+			None => Some(0.5),
+		}
+	}
 
-		let promotion = if let Some(n) = move_text.get(cur_index..(cur_index + 1)) {
-			if n == "=" {
-				if let Some(s) = move_text.get((cur_index + 1)..(cur_index + 2)) {
-					match s {
-						"N" => {
-							cur_index += 2;
-							Some(Piece::Knight)
-						}
-						"B" => {
-							cur_index += 2;
-							Some(Piece::Bishop)
-						}
-						"R" => {
-							cur_index += 2;
-							Some(Piece::Rook)
-						}
-						"Q" => {
-							cur_index += 2;
-							Some(Piece::Queen)
-						}
-						_ => None,
-					}
-				} else {
-					None
-				}
-			} else {
-				None
-			}
-		} else {
-			None
-		};
+	/// The value of the PGN `Result` tag
+	pub fn pgn_result(self) -> &'static str {
+		match self.winner() {
+			Some(Color::White) => "1-0",
+			Some(Color::Black) => "0-1",
+			None if self == Termination::Aborted => "*",
+			None => "1/2-1/2",
+		}
+	}
 
-		if let Some(s) = move_text.get(cur_index..(cur_index + 1)) {
-			let _maybe_check_or_mate = match s {
-				"+" => {
-					cur_index += 1;
-					Some(false)
-				}
-				"#" => {
-					cur_index += 1;
-					Some(true)
-				}
-				_ => None,
-			};
+	/// The value of the PGN `Termination` tag
+	pub fn pgn_termination(self) -> &'static str {
+		match self {
+			Termination::Timeout(_) => "time forfeit",
+			Termination::Abandonment(_) => "abandoned",
+			Termination::Adjudication(_) => "adjudication",
+			Termination::Aborted => "unterminated",
+			_ => "normal",
 		}
+	}
+}
 
-		let ep = if let Some(s) = move_text.get(cur_index..) {
-			s == " e.p."
-		} else {
-			false
+pub struct Clock {
+	pub remaining: [Duration; 2], // Indexed by `Color::to_index`
+	pub increment: Duration,
+	pub turn_started: Option<Instant>, // `None` until the game is accepted
+}
+
+impl Clock {
+	/// Parse a time control such as `5+3` (minutes, then increment in seconds)
+	pub fn parse(control: &str) -> Option<Clock> {
+		let mut parts = control.splitn(2, '+');
+		let minutes = parts.next()?.parse::<u64>().ok()?;
+		let increment = match parts.next() {
+			Some(s) => s.parse::<u64>().ok()?,
+			None => 0,
 		};
+		if minutes == 0 {
+			return None;
+		}
+		Some(Clock {
+			remaining: [Duration::from_secs(minutes * 60); 2],
+			increment: Duration::from_secs(increment),
+			turn_started: None,
+		})
+	}
 
-		//if ep {
-		//    cur_index += 5;
-		//}
+	pub fn start(&mut self) {
+		self.turn_started = Some(Instant::now());
+	}
 
-		// Ok, now we have all the data from the SAN move, in the following structures
-		// moving_piece, source_rank, source_file, taks, dest, promotion, maybe_check_or_mate, and
-		// ep
+	/// Time left for `color`, counting down if it is their turn
+	pub fn remaining(&self, color: Color, to_move: Color) -> Duration {
+		let stored = self.remaining[color.to_index()];
+		match self.turn_started {
+			Some(start) if color == to_move => stored.checked_sub(start.elapsed()).unwrap_or_default(),
+			_ => stored,
+		}
+	}
 
-		let mut found_move: Option<ChessMove> = None;
-		for m in &mut MoveGen::new_legal(board) {
-			// check that the move has the properties specified
-			if board.piece_on(m.get_source()) != Some(moving_piece) {
-				continue;
-			}
+	/// Stop `color`'s clock after they move. Returns false if their time had already run out
+	pub fn punch(&mut self, color: Color) -> bool {
+		let left = self.remaining(color, color);
+		if left == Duration::default() {
+			return false;
+		}
+		self.remaining[color.to_index()] = left + self.increment;
+		self.turn_started = Some(Instant::now());
+		true
+	}
+}
 
-			if let Some(rank) = source_rank {
-				if m.get_source().get_rank() != rank {
-					continue;
-				}
-			}
+pub fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	format!("{}:{:02}", secs / 60, secs % 60)
+}
 
-			if let Some(file) = source_file {
-				if m.get_source().get_file() != file {
-					continue;
-				}
-			}
+/// Whether neither side could possibly deliver checkmate: bare kings, a single minor piece,
+/// or only bishops which all stand on squares of the same colour
+pub fn insufficient_material(board: &Board) -> bool {
+	if (board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen)).popcnt() > 0 {
+		return false;
+	}
 
-			if m.get_dest() != dest {
-				continue;
-			}
+	let knights = board.pieces(Piece::Knight).popcnt();
+	let bishops = *board.pieces(Piece::Bishop);
+	if knights + bishops.popcnt() <= 1 {
+		return true;
+	}
 
-			if m.get_promotion() != promotion {
-				continue;
-			}
+	knights == 0 && {
+		let mut colours = bishops.map(|sq| (sq.get_rank().to_index() + sq.get_file().to_index()) % 2);
+		let first = colours.next();
+		colours.all(|c| Some(c) == first)
+	}
+}
 
-			if found_move.is_some() {
-				// There's more than one move which fits the properties
-				return Err(MoveError::Ambiguous);
-			}
+pub struct Premove {
+	pub condition: Option<String>, // The opponent's move this answers, in SAN; `None` answers any move
+	pub reply: String,
+}
 
-			// takes is complicated, because of e.p.
-			if !takes && board.piece_on(m.get_dest()).is_some() {
-				continue;
-			}
+/// What a move did, worked out from the board it was played on
+#[derive(Clone, Copy)]
+pub struct MoveFacts {
+	pub capture: bool,
+	pub en_passant: bool,
+	pub check: bool,
+	pub castle: bool,
+	pub promotion: Option<Piece>,
+}
 
-			if !ep && takes && board.piece_on(m.get_dest()).is_none() {
-				continue;
-			}
+impl MoveFacts {
+	pub fn of(board: &Board, mv: ChessMove) -> MoveFacts {
+		let source = mv.get_source();
+		let dest = mv.get_dest();
+		let piece = board.piece_on(source);
+		// A pawn changing file onto an empty square can only be capturing en passant
+		let en_passant = piece == Some(Piece::Pawn) && source.get_file() != dest.get_file() && board.piece_on(dest).is_none();
+		MoveFacts {
+			capture: board.piece_on(dest).is_some() || en_passant,
+			en_passant,
+			check: board.make_move_new(mv).checkers().popcnt() > 0,
+			castle: piece == Some(Piece::King) && (source.get_file().to_index() as i32 - dest.get_file().to_index() as i32).abs() == 2,
+			promotion: mv.get_promotion(),
+		}
+	}
+}
 
-			found_move = Some(m);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use discord_chess::san::play_line;
+
+	#[test]
+	fn orientation_settings() {
+		assert!(Orientation::from_setting("white") == Orientation::White);
+		assert!(Orientation::from_setting("own") == Orientation::Own);
+		assert!(Orientation::from_setting("toMove") == Orientation::ToMove);
+		assert!(Orientation::from_setting("") == Orientation::ToMove);
+
+		assert_eq!(Orientation::White.side(Some(Color::Black), Color::Black), Color::White);
+		assert_eq!(Orientation::Own.side(Some(Color::Black), Color::White), Color::Black);
+		assert_eq!(Orientation::Own.side(None, Color::Black), Color::White);
+		assert_eq!(Orientation::ToMove.side(Some(Color::White), Color::Black), Color::Black);
+	}
+
+	/// A game from the starting position with `moves`, in SAN, played
+	fn play(moves: &str) -> ChannelGame {
+		let mut gm = ChannelGame::new();
+		for mv in play_line(&Board::default(), moves).unwrap_or_else(|e| panic!("{}", e)).1 {
+			gm.game.make_move(mv);
 		}
+		gm
+	}
 
-		found_move.ok_or(MoveError::Illegal)
+	#[test]
+	fn confirmations_are_kept_per_player() {
+		let mut gm = ChannelGame::new();
+		let pending = |action| Some(PendingConfirmation { action, prompt: MessageId(1), requested: Instant::now() });
+		gm.pending_confirmations = [pending(Confirmable::Resign), pending(Confirmable::AcceptDraw)];
+		assert!(!gm.take_confirmation(Color::White, Confirmable::AcceptDraw));
+		assert!(gm.take_confirmation(Color::Black, Confirmable::AcceptDraw));
+		assert!(gm.take_confirmation(Color::White, Confirmable::Resign));
+		assert!(!gm.take_confirmation(Color::White, Confirmable::Resign));
 	}
-}
\ No newline at end of file
+
+	fn premove(condition: Option<&str>, reply: &str) -> Premove {
+		Premove { condition: condition.map(str::to_owned), reply: reply.to_owned() }
+	}
+
+	#[test]
+	fn conditional_moves_win_over_premoves() {
+		let mut gm = play("e4");
+		let before = gm.game.current_position();
+		gm.premoves = vec![premove(None, "d4"), premove(Some("e5"), "Nf3"), premove(Some("c5"), "Nc3")];
+		let played = ChessMove::new(Square::C7, Square::C5, None);
+		assert_eq!(gm.take_premove(&before, played).unwrap().reply, "Nc3");
+		assert!(gm.premoves.is_empty());
+
+		gm.premoves = vec![premove(Some("e5"), "Nf3"), premove(None, "d4")];
+		let played = ChessMove::new(Square::D7, Square::D5, None);
+		assert_eq!(gm.take_premove(&before, played).unwrap().reply, "d4");
+		assert!(gm.premoves.is_empty());
+	}
+
+	#[test]
+	fn unanswered_conditional_moves_lapse() {
+		let mut gm = play("e4");
+		let before = gm.game.current_position();
+		gm.premoves = vec![premove(Some("e5"), "Nf3"), premove(Some("Qh4"), "g3")]; // Qh4 isn't legal, so never matches
+		assert!(gm.take_premove(&before, ChessMove::new(Square::C7, Square::C5, None)).is_none());
+		assert!(gm.premoves.is_empty());
+		assert!(gm.take_premove(&before, ChessMove::new(Square::E7, Square::E5, None)).is_none());
+	}
+
+	#[test]
+	fn premoves_made_illegal_lapse() {
+		// A premove is taken whatever the opponent played, and is then only played if it's still legal
+		for &(answer, legal) in &[("d5", true), ("e5", false)] {
+			let mut gm = play("e4");
+			let before = gm.game.current_position();
+			gm.premoves = vec![premove(None, "exd5")];
+			let mv = play_line(&before, answer).unwrap_or_else(|e| panic!("{}", e)).1[0];
+			gm.game.make_move(mv);
+			let taken = gm.take_premove(&before, mv).unwrap();
+			assert_eq!(<ChessMove as FromSan>::from_san(&gm.game.current_position(), &taken.reply).is_ok(), legal);
+		}
+	}
+
+	#[test]
+	fn premoves_can_capture_en_passant() {
+		let mut gm = play("e4 a6 e5");
+		let before = gm.game.current_position();
+		gm.premoves = vec![premove(Some("d5"), "exd6")];
+		let mv = ChessMove::new(Square::D7, Square::D5, None);
+		gm.game.make_move(mv);
+		let taken = gm.take_premove(&before, mv).unwrap();
+		let reply = <ChessMove as FromSan>::from_san(&gm.game.current_position(), &taken.reply).ok();
+		assert!(reply == Some(ChessMove::new(Square::E5, Square::D6, None)));
+	}
+
+	#[test]
+	fn time_controls() {
+		assert!(Clock::parse("0+5").is_none());
+		assert!(Clock::parse("5+").is_none());
+		assert!(Clock::parse("casual").is_none());
+
+		let mut clock = Clock::parse("5+3").unwrap();
+		assert_eq!(clock.remaining(Color::White, Color::White), Duration::from_secs(300)); // Not running until started
+		clock.start();
+		assert!(clock.punch(Color::White));
+		assert!(clock.remaining(Color::White, Color::Black) > Duration::from_secs(300));
+		assert_eq!(clock.remaining(Color::Black, Color::White), Duration::from_secs(300));
+
+		clock.remaining[Color::Black.to_index()] = Duration::default();
+		assert!(!clock.punch(Color::Black));
+	}
+
+	#[test]
+	fn insufficient_material_cases() {
+		let insufficient = |fen: &str| insufficient_material(&fen.parse().unwrap());
+		assert!(insufficient("8/8/4k3/8/8/3K4/8/8 w - - 0 1"));
+		assert!(insufficient("8/8/4k3/8/8/3KN3/8/8 w - - 0 1"));
+		assert!(insufficient("8/8/4k3/8/8/3KB3/8/8 w - - 0 1"));
+		assert!(insufficient("8/8/3bk3/8/8/3KB3/8/8 w - - 0 1")); // Both bishops on dark squares
+		assert!(!insufficient("8/8/2b1k3/8/8/3KB3/8/8 w - - 0 1")); // Bishops on opposite colours
+		assert!(!insufficient("8/8/4k3/8/8/3KNN2/8/8 w - - 0 1"));
+		assert!(!insufficient("8/8/4k3/8/8/3KBN2/8/8 w - - 0 1"));
+		assert!(!insufficient("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1"));
+		assert!(!insufficient("8/8/4k3/8/8/3K4/8/R7 w - - 0 1"));
+	}
+
+	#[test]
+	fn fivefold_repetition() {
+		let shuffle = "Nf3 Nf6 Ng1 Ng8 ";
+		let gm = play(&shuffle.repeat(3));
+		assert_eq!(gm.repetitions(), 4);
+		assert!(gm.detect_termination().is_none());
+
+		let gm = play(&shuffle.repeat(4));
+		assert_eq!(gm.repetitions(), 5);
+		assert!(gm.detect_termination() == Some(Termination::Automatic(AutomaticDraw::FivefoldRepetition)));
+	}
+
+	#[test]
+	fn seventy_five_move_rule() {
+		// Wander the pieces without moving a pawn, capturing or repeating a position
+		let mut gm = play("e4 e5");
+		let mut seen = vec![gm.game.current_position().get_hash()];
+		while gm.halfmove_clock() < 150 {
+			assert!(gm.detect_automatic_draw().is_none());
+			let position = gm.game.current_position();
+			let mv = MoveGen::new_legal(&position)
+				.find(|&mv| {
+					let next = position.make_move_new(mv);
+					position.piece_on(mv.get_source()) != Some(Piece::Pawn) && position.piece_on(mv.get_dest()).is_none()
+						&& next.status() == BoardStatus::Ongoing && !seen.contains(&next.get_hash())
+				})
+				.expect("ran out of fresh quiet moves");
+			seen.push(position.make_move_new(mv).get_hash());
+			gm.game.make_move(mv);
+		}
+		assert!(gm.detect_termination() == Some(Termination::Automatic(AutomaticDraw::SeventyFiveMoveRule)));
+	}
+}
@@ -0,0 +1,6 @@
+//! Board drawing and move notation, shared by the bot, `chess-render` and the rendering benchmark
+pub mod draw;
+pub mod pieces;
+pub mod render;
+pub mod san;
+pub mod theme;
@@ -3,17 +3,22 @@
 use std::collections::{HashSet,HashMap};
 use std::sync::{RwLock};
 use std::borrow::Cow;
+use std::time::Instant;
 
 use serenity::{
 	client::Client,
 	model::{
 		channel::{
 			Message,
-			GuildChannel
+			GuildChannel,
+			Reaction,
+			ReactionType
 		},
 		gateway::Ready,
 		id::{
 			ChannelId,
+			GuildId,
+			MessageId,
 			UserId
 		}
 	},
@@ -21,6 +26,7 @@ use serenity::{
 	framework::standard::{
 		StandardFramework,
 		CommandResult,
+		CommandError,
 		Args,
 		Delimiter,
 		HelpOptions,
@@ -36,8 +42,8 @@ use serenity::{
 
 use rand::prelude::*;
 use regex::Regex;
-use raster::{Image,PositionMode,BlendMode};
-use chess::{Color,ChessMove,Rank,File,Piece,Square,GameResult};
+use raster::Image;
+use chess::{Board,Color,ChessMove};
 
 use chashmap::CHashMap;
 
@@ -47,6 +53,20 @@ use config::*;
 mod game;
 use game::*;
 
+mod record;
+use record::*;
+
+mod rating;
+use rating::*;
+
+use discord_chess::{draw::*, pieces::*, render::*, san::*, theme::*};
+
+mod achievement;
+use achievement::*;
+
+mod opening;
+use opening::*;
+
 //MARK: Statics
 lazy_static! {
 	static ref CONFIG: Config = Config {
@@ -56,39 +76,28 @@ lazy_static! {
 
 	static ref GAMES: CHashMap<ChannelId, ChannelGame> = CHashMap::<_, _>::new();
 	static ref USERS: CHashMap<UserId, UserStats> = CHashMap::<_, _>::new();
+	static ref GUILD_USERS: CHashMap<(GuildId, UserId), UserStats> = CHashMap::<_, _>::new();
+	static ref ACHIEVEMENTS: CHashMap<UserId, Vec<Achievement>> = CHashMap::<_, _>::new(); // In the order they were earned
+	static ref HISTORY: RwLock<Vec<GameRecord>> = RwLock::new(Vec::new());
+	static ref RATINGS: CHashMap<(GuildId, UserId), PlayerRatings> = CHashMap::<_, _>::new();
 
-	static ref BOARD_IMG_WHITE: Image = raster::open("res/board_annotated_white.png").unwrap();
-	static ref BOARD_IMG_BLACK: Image = raster::open("res/board_annotated_black.png").unwrap();
+	static ref OPENINGS: OpeningBook = OpeningBook::load("res/openings.tsv");
 
-	static ref PAWNS_IMG: Image = raster::open("res/pawns.png").unwrap();
-	static ref KNIGHTS_IMG: Image = raster::open("res/knights.png").unwrap();
-	static ref BISHOPS_IMG: Image = raster::open("res/bishops.png").unwrap();
-	static ref ROOKS_IMG: Image = raster::open("res/rooks.png").unwrap();
-	static ref QUEENS_IMG: Image = raster::open("res/queens.png").unwrap();
-	static ref KINGS_IMG: Image = raster::open("res/kings.png").unwrap();
+	static ref RENDERER: Renderer = Renderer::new(FONT_IMG.clone(), PieceSet::load_all("res/pieces"));
 
-	static ref YELLOW_SQUARE: Image = {
-		let mut img = raster::Image::blank(80, 80);
-		raster::editor::fill(&mut img, raster::Color::rgb(255, 255, 127)).unwrap();
-		img
-	};
-	static ref RED_SQUARE: Image = {
-		let mut img = raster::Image::blank(80, 80);
-		raster::editor::fill(&mut img, raster::Color::rgb(255, 83, 83)).unwrap();
-		img
-	};
+	static ref FONT_IMG: Image = raster::open("res/font.png").unwrap();
 }
 
 #[group]
 #[help_available]
 #[only_in(guilds)]
-#[commands(play, accept, decline, cancel, preferences, statistics)]
+#[commands(play, accept, decline, cancel, preferences, statistics, leaderboard, games, game, replay, profile)]
 struct General;
 
 #[group]
 #[help_available]
 #[only_in(guilds)]
-#[commands(board, draw, resign)]
+#[commands(board, diagram, draw, decline_draw, resign, premove, conditional)]
 struct Game;
 
 #[group]
@@ -110,43 +119,42 @@ impl EventHandler for Handler {
 		println!("Ready");
 	}
 
+	fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+		if reaction.emoji != ReactionType::Unicode(CONFIRM_EMOJI.to_string()) {
+			return;
+		}
+		if let Some(mut gm) = GAMES.get_mut(&reaction.channel_id) {
+			let color = match gm.player_color(reaction.user_id) {
+				Some(color) => color,
+				None => return,
+			};
+			let action = match &gm.pending_confirmations[color.to_index()] {
+				Some(pending) if pending.prompt == reaction.message_id => pending.action,
+				_ => return,
+			};
+			if gm.state == ChannelGameState::Running && gm.take_confirmation(color, action) {
+				let ch = reaction.channel(&ctx).unwrap().guild().unwrap();
+				match action {
+					Confirmable::Resign => resign_game(&ctx, &mut gm, color, &ch.read()).unwrap(),
+					Confirmable::AcceptDraw => if gm.draw_offer == Some(!color) {
+						accept_draw(&ctx, &mut gm, &ch.read()).unwrap();
+					},
+				}
+			}
+		}
+	}
+
 	//MARK: Message handler
 	fn message(&self, ctx: Context, msg: Message) {
 		if let Some(mut gm) = GAMES.get_mut(&msg.channel_id) {
-			if gm.state == ChannelGameState::Running && (match gm.game.side_to_move() { Color::White => gm.white, Color::Black => gm.black }) == msg.author.id {
-				lazy_static! {
-					static ref MOVE_REGEX: Regex = Regex::new(r"^[KQBNR]?[a-h]?[1-8]?x?[a-h][1-8](?:=[BQRN])?[\+#]?( e.p.)?$").unwrap();
-					static ref CASTLE_REGEX: Regex = Regex::new("^O-O(-O)?$").unwrap();
-				}
-
-				if MOVE_REGEX.is_match(&msg.content) || CASTLE_REGEX.is_match(&msg.content) {
-					let result = ChessMove::from_san(&gm.game.current_position(), &msg.content);
-					match result {
-						Err(game::MoveError::IllFormed) => { msg.reply(ctx, format!("Ill-formed move: {}", msg.content)).unwrap(); },
-						Err(game::MoveError::Illegal) => { msg.reply(ctx, format!("Illegal move: {}", msg.content)).unwrap(); }
-						Err(game::MoveError::Ambiguous) => { msg.reply(ctx, format!("Ambiguous move: {}", msg.content)).unwrap(); }
-						Ok(mv) => {
-							gm.game.make_move(mv);
-							gm.last_move = Some(mv);
-							gm.draw_offer = None;
-							post_board(&ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read()).unwrap();
-							
-							USERS.alter(msg.author.id, |opt| match opt {
-								Some(stats) => Some(stats),
-								None=> Some(UserStats::default())
-							});
-							let mut author_stats = USERS.get_mut(&msg.author.id).unwrap();
-							author_stats.moves_made += 1;
-							if msg.content.contains('x') { // All capture moves must contain 'x', lest they be marked illegal
-								author_stats.pieces_captured += 1;
-							}
-							if gm.game.current_position().checkers().popcnt() > 0 { // This move put the opponent's king in check
-								author_stats.checks_given += 1;
-							}
-							std::mem::drop(author_stats);
-
-							check_game_result(&mut gm);
-						}
+			if gm.state == ChannelGameState::Running && gm.player(gm.game.side_to_move()) == msg.author.id && is_move_text(&msg.content) {
+				let result = <ChessMove as FromSan>::from_san(&gm.game.current_position(), &msg.content);
+				match result {
+					Err(MoveError::IllFormed) => { msg.reply(ctx, format!("Ill-formed move: {}", msg.content)).unwrap(); },
+					Err(MoveError::Illegal) => { msg.reply(ctx, format!("Illegal move: {}", msg.content)).unwrap(); }
+					Err(MoveError::Ambiguous) => { msg.reply(ctx, format!("Ambiguous move: {}", msg.content)).unwrap(); }
+					Ok(mv) => {
+						play_move(&ctx, &mut gm, mv, &msg.content, &msg.channel(&ctx).unwrap().guild().unwrap().read()).unwrap();
 					}
 				}
 			}
@@ -160,17 +168,9 @@ fn main() {
 	print!("Loading images...");
 	std::io::stdout().lock().flush().unwrap();
 
-	lazy_static::initialize(&BOARD_IMG_WHITE);
-	lazy_static::initialize(&BOARD_IMG_BLACK);
-	lazy_static::initialize(&PAWNS_IMG);
-	lazy_static::initialize(&KNIGHTS_IMG);
-	lazy_static::initialize(&BISHOPS_IMG);
-	lazy_static::initialize(&ROOKS_IMG);
-	lazy_static::initialize(&QUEENS_IMG);
-	lazy_static::initialize(&KINGS_IMG);
-
-	lazy_static::initialize(&YELLOW_SQUARE);
-	lazy_static::initialize(&RED_SQUARE);
+	lazy_static::initialize(&FONT_IMG);
+	lazy_static::initialize(&RENDERER);
+	lazy_static::initialize(&OPENINGS);
 
 	println!(" Done.");
 
@@ -188,111 +188,88 @@ fn main() {
 
 	client.with_framework(StandardFramework::new().configure(|c| c.prefix("c>").on_mention(Some(bot_id)).owners(owners)).group(&GENERAL_GROUP).group(&GAME_GROUP).group(&MANAGERIAL_GROUP).group(&OWNER_GROUP).help(&MAIN_HELP));
 
+	std::thread::spawn(|| loop {
+		std::thread::sleep(RATING_PERIOD);
+		close_rating_period();
+	});
+
 	if let Err(reason) = client.start() {
 		panic!("An error occured when starting the client: {:?}", reason);
 	}
 }
 
-//MARK: Board
-fn post_board(ctx: &Context, gm: &ChannelGame, ch: &GuildChannel) -> CommandResult {
-	CONFIG.lazy_guild(ch.guild_id);
-	CONFIG.lazy_user(gm.black);
+//MARK: Moves
+fn is_move_text(text: &str) -> bool {
+	lazy_static! {
+		static ref MOVE_REGEX: Regex = Regex::new(r"^[KQBNR]?[a-h]?[1-8]?x?[a-h][1-8](?:=[BQRN])?[\+#]?( e.p.)?$").unwrap();
+		static ref CASTLE_REGEX: Regex = Regex::new("^O-O(-O)?$").unwrap();
+	}
 
-	ch.broadcast_typing(ctx)?;
+	MOVE_REGEX.is_match(text) || CASTLE_REGEX.is_match(text)
+}
 
-	let use_black = gm.game.side_to_move() == Color::Black && CONFIG.user_prefs.read().unwrap().get(&gm.black).unwrap().settings.get("flipIfBlack").unwrap().parse::<bool>().unwrap_or(false);
-	let mut board = if use_black { BOARD_IMG_BLACK.clone() } else { BOARD_IMG_WHITE.clone() };
+/// Play `mv`, entered as `text`, for the side to move, then answer it with the opponent's premove if they queued one
+fn play_move(ctx: &Context, gm: &mut ChannelGame, mv: ChessMove, text: &str, ch: &GuildChannel) -> CommandResult {
+	let mover = gm.game.side_to_move();
+	let in_time = match &mut gm.clock {
+		Some(clock) => clock.punch(mover),
+		None => true,
+	};
+	if !in_time {
+		gm.termination = Some(Termination::Timeout(!mover));
+		check_game_result(gm);
+		post_board(ctx, gm, ch)?;
+		return Ok(());
+	}
 
-	const RANK_INDEX: [Rank; 8] = [Rank::Eighth, Rank::Seventh, Rank::Sixth, Rank::Fifth, Rank::Fourth, Rank::Third, Rank::Second, Rank::First];
-	const FILE_INDEX: [File; 8] = [File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H];
+	let before = gm.game.current_position();
+	gm.game.make_move(mv);
+	gm.last_move = Some(mv);
+	gm.termination = gm.detect_termination();
+	if gm.draw_offer == Some(!mover) { // Moving instead of accepting declines the offer
+		gm.draw_offer = None;
+		ch.say(ctx, "The draw offer has lapsed")?;
+	}
 
-	for (y, &rank) in RANK_INDEX.iter().enumerate() {
-		for (x, &file) in FILE_INDEX.iter().enumerate() {
-			let posx: usize;
-			let posy: usize;
-			if use_black {
-				posx = 7 - x;
-				posy = 7 - y;
-			} else {
-				posx = x;
-				posy = y;
-			}
+	let facts = MoveFacts::of(&before, mv);
+	update_stats(gm.guild, gm.player(mover), |stats| {
+		stats.moves_made += 1;
+		stats.pieces_captured += facts.capture as u32;
+		stats.en_passant_captures += facts.en_passant as u32;
+		stats.checks_given += facts.check as u32;
+		stats.castles += facts.castle as u32;
+		stats.promotions += facts.promotion.is_some() as u32;
+	});
 
-			let square = Square::make_square(rank, file);
-			if let Some(last_move) = gm.last_move {
-				if square == last_move.get_source() || square == last_move.get_dest() {
-					board = raster::editor::blend(&board, &YELLOW_SQUARE, BlendMode::Normal, 1.0, PositionMode::TopLeft, (40 + posx * 80) as i32, (40 + posy * 80) as i32).unwrap();
-				}
-			}
+	check_game_result(gm);
+	post_board(ctx, gm, ch)?;
 
-			if gm.game.current_position().checkers().popcnt() > 0
-				&& square == gm.game.current_position().king_square(gm.game.side_to_move()) {
-				board = raster::editor::blend(&board, &RED_SQUARE, BlendMode::Normal, 1.0, PositionMode::TopLeft, (40 + posx * 80) as i32, (40 + posy * 80) as i32).unwrap();
-			}
-			
-			if let Some(piece) = gm.game.current_position().piece_on(square) {
-				let (mut piece_img, white_ctr, black_ctr) = match piece {
-					Piece::Pawn => (PAWNS_IMG.clone(), 46, 116),
-					Piece::Knight => (KNIGHTS_IMG.clone(), 45, 120),
-					Piece::Bishop => (BISHOPS_IMG.clone(), 43, 118),
-					Piece::Rook => (ROOKS_IMG.clone(), 45, 116),
-					Piece::Queen => (QUEENS_IMG.clone(), 41, 120),
-					Piece::King => (KINGS_IMG.clone(), 42, 118),
-				};
-				raster::editor::crop(
-					&mut piece_img,
-					80, 80, PositionMode::TopLeft,
-					if gm.game.current_position().color_on(square) == Some(Color::White) {
-						white_ctr - 40
-					} else {
-						black_ctr - 40
-					},
-					0
-				).unwrap();
-				// raster::editor::resize(&mut piece_img, 80, 80, ResizeMode::Exact).unwrap();
-				board = raster::editor::blend(&board, &piece_img, BlendMode::Normal, 1.0, PositionMode::TopLeft, (40 + posx * 80) as i32, (40 + posy * 80) as i32).unwrap();
+	if gm.state == ChannelGameState::Running && !gm.premoves.is_empty() {
+		let opponent = gm.player(!mover);
+		match gm.take_premove(&before, mv) {
+			Some(premove) => match <ChessMove as FromSan>::from_san(&gm.game.current_position(), &premove.reply) {
+				Ok(reply) => {
+					ch.say(ctx, format!("<@{}>, your premove {} has been played", opponent, premove.reply))?;
+					play_move(ctx, gm, reply, &premove.reply, ch)?;
+				}
+				Err(_) => {
+					ch.say(ctx, format!("<@{}>, your premove {} isn't legal any more, so it has been discarded", opponent, premove.reply))?;
+				}
+			},
+			None => {
+				ch.say(ctx, format!("<@{}>, none of your conditional moves answered {}, so they have been discarded", opponent, text))?;
 			}
 		}
 	}
 
-	let bytes = {
-		use png::{Encoder, Compression, ColorType, BitDepth};
-
-		let mut bytes = Vec::<u8>::new();
-		let mut encoder = Encoder::new(&mut bytes, board.width as u32, board.height as u32);
-		encoder.set_color(ColorType::RGBA);
-		encoder.set_depth(BitDepth::Eight);
-		encoder.set_compression(Compression::Fast);
-
-		let mut writer = encoder.write_header().unwrap();
-		writer.write_image_data(&board.bytes).unwrap();
-		std::mem::drop(writer);
-
-		bytes
-	};
+	Ok(())
+}
 
-	let sent = ch.send_message(
-		ctx,
-		|c| {
-			c
-			.content(format!("{} to play", match gm.game.side_to_move() { Color::White => "White", Color::Black => "Black" }))
-			.add_file(AttachmentType::Bytes { data: Cow::from(&bytes), filename: String::from("board.png") });
-			if let Some(result) = gm.game.result() {
-				c.content(format!("{} to play{}", match gm.game.side_to_move() { Color::White => "White", Color::Black => "Black" },
-				match result {
-					GameResult::WhiteCheckmates | GameResult::BlackCheckmates => " is checkmated",
-					GameResult::WhiteResigns => match gm.game.side_to_move() { Color::White => " has resigned", Color::Black => "; White has resigned" },
-					GameResult::BlackResigns => match gm.game.side_to_move() { Color::Black => " has resigned", Color::White => "; Black has resigned" },
-					GameResult::Stalemate => " is stalemated",
-					GameResult::DrawAccepted => "; Drawn by agreement",
-					GameResult::DrawDeclared => "; Draw was declared"
-				}));
-			} else if gm.game.can_declare_draw() {
-				c.content(format!("{} to play can declare draw", match gm.game.side_to_move() { Color::White => "White", Color::Black => "Black" }));
-			}
-			c
-		}
-	)?.id;
+//MARK: Board
+/// Post the players' board after a change to the game, deleting older ones as the guild has chosen
+fn post_board(ctx: &Context, gm: &ChannelGame, ch: &GuildChannel) -> CommandResult {
+	let viewer = gm.player(gm.game.side_to_move());
+	let sent = send_board(ctx, gm, ch, viewer, board_orientation(gm, viewer), true)?;
 	match &**CONFIG.guild_settings.read()?.get(&ch.guild_id).unwrap().settings.get("deleteOld").unwrap() {
 		"onNext" => {
 			let mut lock = gm.old_boards.lock()?;
@@ -314,53 +291,305 @@ fn post_board(ctx: &Context, gm: &ChannelGame, ch: &GuildChannel) -> CommandResu
 	Ok(())
 }
 
-fn check_game_result(gm: &mut ChannelGame) {
-	if let Some(result) = gm.game.result() {
-		USERS.alter(gm.white, |opt| match opt {
-			Some(stats) => Some(stats),
-			None=> Some(UserStats::default())
-		});
-		USERS.alter(gm.black, |opt| match opt {
-			Some(stats) => Some(stats),
-			None=> Some(UserStats::default())
+/// Post `gm`'s board for someone who asked for it, leaving the players' boards alone
+fn post_requested_board(ctx: &Context, gm: &ChannelGame, ch: &GuildChannel, viewer: UserId, orientation: Color) -> CommandResult {
+	send_board(ctx, gm, ch, viewer, orientation, false)?;
+	Ok(())
+}
+
+/// The side `viewer` wants at the bottom of `gm`'s board
+fn board_orientation(gm: &ChannelGame, viewer: UserId) -> Color {
+	Orientation::from_setting(&CONFIG.user_setting(viewer, "orientation")).side(gm.player_color(viewer), gm.game.side_to_move())
+}
+
+/// Send `gm`'s board drawn the way `viewer` likes it, with `orientation` at the bottom.
+/// The players' board also carries what only needs saying once: new achievements, and the PGN once the game has ended
+fn send_board(ctx: &Context, gm: &ChannelGame, ch: &GuildChannel, viewer: UserId, orientation: Color, for_players: bool) -> Result<MessageId, CommandError> {
+	CONFIG.lazy_guild(ch.guild_id);
+
+	ch.broadcast_typing(ctx)?;
+
+	let users = [gm.white.to_user(ctx)?, gm.black.to_user(ctx)?];
+	let names = [users[0].name.clone(), users[1].name.clone()];
+	let options = RenderOptions {
+		orientation,
+		last_move: gm.last_move,
+		panel: if CONFIG.user_flag(viewer, "sidePanel") { Some(Panel { names: names.clone(), moves: gm.moves() }) } else { None },
+		..board_options(gm.guild, viewer)
+	};
+	let bytes = RENDERER.render_file(&gm.game.current_position(), &options);
+
+	let mut header = Vec::new();
+	for &color in &[Color::White, Color::Black] {
+		let name = &names[color.to_index()];
+		header.push(match (gm.rated, gm.rating_changes) {
+			(false, _) => format!("**{}**", name),
+			(true, Some(changes)) => format!("**{}** ({} {:+.0})", name, rating_label(gm.guild, gm.player(color)), changes[color.to_index()]),
+			(true, None) => format!("**{}** ({})", name, rating_label(gm.guild, gm.player(color))),
 		});
-		let mut white_stats = USERS.get_mut(&gm.white).unwrap();
-		let mut black_stats = USERS.get_mut(&gm.black).unwrap();
-		match result {
-			GameResult::WhiteCheckmates => {
-				white_stats.won_checkmate += 1;
-				black_stats.lost_checkmate += 1;
-			},
-			GameResult::BlackResigns => {
-				white_stats.won_default += 1;
-				black_stats.lost_resigned += 1;
-			},
-			GameResult::BlackCheckmates => {
-				white_stats.lost_checkmate += 1;
-				black_stats.won_checkmate += 1;
-			},
-			GameResult::WhiteResigns => {
-				white_stats.lost_resigned += 1;
-				black_stats.won_default += 1;
-			},
-			GameResult::Stalemate => {
-				white_stats.drawn_stalemate += 1;
-				black_stats.drawn_stalemate += 1;
-			},
-			GameResult::DrawAccepted => {
-				white_stats.drawn_agreement += 1;
-				black_stats.drawn_agreement += 1;
-			},
-			GameResult::DrawDeclared => {
-				white_stats.drawn_declared += 1;
-				black_stats.drawn_declared += 1;
-			},
+	}
+	let mut header = format!("{} vs {}{}\n", header[0], header[1], if gm.rated { "" } else { " (casual)" });
+	if let Some(opening) = OPENINGS.classify(&gm.moves()) {
+		header.push_str(&format!("{} {}\n", opening.eco, opening.name));
+	}
+
+	let to_move = gm.game.side_to_move();
+	let mut content = if let Some(termination) = gm.termination {
+		format!("{} to play; {}", color_name(to_move), termination.description())
+	} else if let Some(claim) = gm.claimable_draw() {
+		format!("{} to play can claim a draw by {}", color_name(to_move), claim.description())
+	} else if let Some(offerer) = gm.draw_offer {
+		format!("{} to play; {} has offered a draw", color_name(to_move), color_name(offerer))
+	} else {
+		format!("{} to play", color_name(to_move))
+	};
+	if let (Some(clock), None) = (&gm.clock, gm.termination) {
+		content.push_str(&format!(
+			" (White {}, Black {})",
+			format_duration(clock.remaining(Color::White, to_move)),
+			format_duration(clock.remaining(Color::Black, to_move))
+		));
+	}
+	let mut pgn = None;
+	if for_players {
+		if gm.termination.is_some() {
+			content.push_str("\n`c>replay` posts an animated replay of this game");
+		}
+		for (user, achievement) in &gm.new_achievements {
+			content.push_str(&format!("\n🏅 <@{}> earned **{}**: {}", user, achievement.name(), achievement.description()));
+		}
+		// The stored record, so the PGN matches what `c>game` shows
+		if let Some(index) = gm.record {
+			pgn = Some(HISTORY.read()?[index].to_pgn(&users[0].tag(), &users[1].tag()));
+		}
+	}
+
+	let sent = ch.send_message(
+		ctx,
+		|c| {
+			c
+			.content(header + &content)
+			.add_file(AttachmentType::Bytes { data: Cow::from(&bytes[..]), filename: format!("board.{}", options.format.extension()) });
+			if let Some(pgn) = &pgn {
+				c.add_file(AttachmentType::Bytes { data: Cow::from(pgn.as_bytes()), filename: String::from("game.pgn") });
+			}
+			c
+		}
+	)?.id;
+
+	Ok(sent)
+}
+
+const TEXT_COLOR: (u8, u8, u8) = (255, 254, 241);
+const BACKGROUND_COLOR: (u8, u8, u8) = (42, 42, 42);
+const BAR_COLOR: (u8, u8, u8) = (91, 181, 81);
+const DRAW_COLOR: (u8, u8, u8) = (140, 140, 140);
+const LOSS_COLOR: (u8, u8, u8) = (196, 78, 68);
+
+/// Draw a page of the leaderboard as rows of rank, name and a bar for the value
+fn leaderboard_image(rows: &[(usize, String, f64, String)], max: f64) -> Image {
+	const ROW_HEIGHT: i32 = 48;
+	const WIDTH: i32 = 640;
+
+	let mut img = Image::blank(WIDTH, ROW_HEIGHT * rows.len() as i32 + 20);
+	let text = raster::Color::rgb(TEXT_COLOR.0, TEXT_COLOR.1, TEXT_COLOR.2);
+	let bar = raster::Color::rgb(BAR_COLOR.0, BAR_COLOR.1, BAR_COLOR.2);
+	raster::editor::fill(&mut img, raster::Color::rgb(BACKGROUND_COLOR.0, BACKGROUND_COLOR.1, BACKGROUND_COLOR.2)).unwrap();
+
+	for (i, (rank, name, value, label)) in rows.iter().enumerate() {
+		let y = 10 + i as i32 * ROW_HEIGHT;
+		draw_text(&mut img, &FONT_IMG, &format!("{:>3}", rank), 10, y + 10, 28, &text);
+		let name: String = name.chars().take(14).collect();
+		draw_text(&mut img, &FONT_IMG, &name, 80, y + 10, 28, &text);
+		let length = if max > 0.0 { (value / max * 200.0) as i32 } else { 0 };
+		fill_rect(&mut img, 340, y + 10, length.max(2), 28, &bar);
+		draw_text(&mut img, &FONT_IMG, label, 350 + length, y + 10, 28, &text);
+	}
+
+	img
+}
+
+/// Draw a player's profile card: a placeholder avatar, their rating, bars for their results,
+/// their favourite opening and their most recent results as `W`, `D` or `L`, newest first
+fn profile_image(user: UserId, name: &str, rating: &str, stats: &UserStats, favourite: Option<&str>, recent: &[char]) -> Image {
+	const WIDTH: i32 = 640;
+	const HEIGHT: i32 = 380;
+	const AVATAR_SIZE: i32 = 120;
+	const BAR_WIDTH: i32 = 300;
+
+	let mut img = Image::blank(WIDTH, HEIGHT);
+	let text = raster::Color::rgb(TEXT_COLOR.0, TEXT_COLOR.1, TEXT_COLOR.2);
+	let result_colors = [
+		raster::Color::rgb(BAR_COLOR.0, BAR_COLOR.1, BAR_COLOR.2),
+		raster::Color::rgb(DRAW_COLOR.0, DRAW_COLOR.1, DRAW_COLOR.2),
+		raster::Color::rgb(LOSS_COLOR.0, LOSS_COLOR.1, LOSS_COLOR.2),
+	];
+	raster::editor::fill(&mut img, raster::Color::rgb(BACKGROUND_COLOR.0, BACKGROUND_COLOR.1, BACKGROUND_COLOR.2)).unwrap();
+
+	// Placeholder avatar: the first letter of their name on a colour picked from their id
+	let id = user.0;
+	let avatar = raster::Color::rgb(64 + (id % 128) as u8, 64 + (id / 128 % 128) as u8, 64 + (id / 16384 % 128) as u8);
+	fill_rect(&mut img, 20, 20, AVATAR_SIZE, AVATAR_SIZE, &avatar);
+	let initial: String = name.chars().take(1).flat_map(char::to_uppercase).collect();
+	draw_text(&mut img, &FONT_IMG, &initial, 20 + AVATAR_SIZE / 2 - 17, 20 + AVATAR_SIZE / 2 - 34, 68, &text);
+
+	let name: String = name.chars().take(26).collect();
+	draw_text(&mut img, &FONT_IMG, &name, 160, 24, 34, &text);
+	draw_text(&mut img, &FONT_IMG, &format!("Rating: {}", rating), 160, 70, 24, &text);
+	draw_text(&mut img, &FONT_IMG, &format!("Games:  {}", stats.games()), 160, 100, 24, &text);
+
+	let counts = [stats.won(), stats.drawn(), stats.lost()];
+	let most = counts.iter().cloned().max().unwrap_or(0);
+	for (i, (&count, label)) in counts.iter().zip(&["Won", "Drawn", "Lost"]).enumerate() {
+		let y = 160 + i as i32 * 36;
+		draw_text(&mut img, &FONT_IMG, label, 20, y, 28, &text);
+		let length = if most > 0 { count as i32 * BAR_WIDTH / most as i32 } else { 0 };
+		fill_rect(&mut img, 120, y, length.max(2), 28, &result_colors[i]);
+		draw_text(&mut img, &FONT_IMG, &count.to_string(), 130 + length, y, 28, &text);
+	}
+
+	let favourite: String = favourite.unwrap_or("-").chars().take(40).collect();
+	draw_text(&mut img, &FONT_IMG, &format!("Favourite: {}", favourite), 20, 278, 24, &text);
+
+	draw_text(&mut img, &FONT_IMG, "Recent:", 20, 326, 24, &text);
+	for (i, &result) in recent.iter().enumerate() {
+		let x = 140 + i as i32 * 40;
+		let color = match result { 'W' => &result_colors[0], 'D' => &result_colors[1], _ => &result_colors[2] };
+		fill_rect(&mut img, x, 320, 34, 34, color);
+		draw_text(&mut img, &FONT_IMG, &result.to_string(), x + 10, 324, 26, &text);
+	}
+
+	img
+}
+
+/// Apply `update` to a user's statistics, both across all servers and in `guild`
+fn update_stats(guild: GuildId, user: UserId, update: impl Fn(&mut UserStats)) {
+	let apply = |opt: Option<UserStats>| {
+		let mut stats = opt.unwrap_or_default();
+		update(&mut stats);
+		Some(stats)
+	};
+	USERS.alter(user, apply);
+	GUILD_USERS.alter((guild, user), apply);
+}
+
+fn check_game_result(gm: &mut ChannelGame) {
+	if let Some(termination) = gm.termination {
+		let length = gm.moves().len();
+		for &color in &[Color::White, Color::Black] {
+			update_stats(gm.guild, gm.player(color), |stats| stats.record(termination, color, length));
+		}
+		for &color in &[Color::White, Color::Black] {
+			let player = gm.player(color);
+			let stats = USERS.get(&player).map(|s| *s).unwrap_or_default();
+			let earned = earned(gm, termination, color, &stats);
+			let mut held = ACHIEVEMENTS.get(&player).map(|a| a.clone()).unwrap_or_default();
+			for achievement in earned {
+				if !held.contains(&achievement) {
+					held.push(achievement);
+					gm.new_achievements.push((player, achievement));
+				}
+			}
+			ACHIEVEMENTS.insert(player, held);
+		}
+		if let (true, Some(score)) = (gm.rated, termination.white_score()) {
+			let white_key = (gm.guild, gm.white);
+			let black_key = (gm.guild, gm.black);
+			let system = guild_rating_system(gm.guild);
+			let white = RATINGS.get(&white_key).map(|r| r.clone()).unwrap_or_default();
+			let black = RATINGS.get(&black_key).map(|r| r.clone()).unwrap_or_default();
+			let (mut new_white, mut new_black) = (white.clone(), black.clone());
+			new_white.record(&black, score);
+			new_black.record(&white, 1.0 - score);
+			gm.rating_changes = Some([
+				new_white.display(system).0 - white.display(system).0,
+				new_black.display(system).0 - black.display(system).0,
+			]);
+			RATINGS.insert(white_key, new_white);
+			RATINGS.insert(black_key, new_black);
 		}
+		let mut history = HISTORY.write().unwrap();
+		history.push(GameRecord::new(gm, termination, &OPENINGS));
+		gm.record = Some(history.len() - 1);
 
 		gm.state = ChannelGameState::Inactive;
 	}
 }
 
+const CONFIRM_EMOJI: char = '✅';
+
+fn request_confirmation(ctx: &Context, msg: &Message, gm: &mut ChannelGame, color: Color, action: Confirmable) -> CommandResult {
+	let command = match action {
+		Confirmable::Resign => "c>resign confirm",
+		Confirmable::AcceptDraw => "c>draw confirm",
+	};
+	let prompt = msg.reply(ctx, format!("Are you sure? React with {} or type `{}` within {} seconds", CONFIRM_EMOJI, command, CONFIRM_TIMEOUT.as_secs()))?;
+	gm.pending_confirmations[color.to_index()] = Some(PendingConfirmation { action, prompt: prompt.id, requested: Instant::now() });
+	if let Err(why) = prompt.react(ctx, CONFIRM_EMOJI) { // Typing the command still confirms
+		println!("Couldn't react to a confirmation prompt: {:?}", why);
+	}
+	Ok(())
+}
+
+fn resign_game(ctx: &Context, gm: &mut ChannelGame, color: Color, ch: &GuildChannel) -> CommandResult {
+	gm.game.resign(color);
+	gm.termination = Some(Termination::Resignation(!color));
+	check_game_result(gm);
+	post_board(ctx, gm, ch)?;
+	Ok(())
+}
+
+fn accept_draw(ctx: &Context, gm: &mut ChannelGame, ch: &GuildChannel) -> CommandResult {
+	gm.draw_offer = None;
+	if !gm.game.accept_draw() { // The offer is no longer recent enough for the game to take it
+		ch.say(ctx, "The draw offer has lapsed")?;
+		return Ok(());
+	}
+	gm.termination = Some(Termination::Agreement);
+	check_game_result(gm);
+	post_board(ctx, gm, ch)?;
+	Ok(())
+}
+
+/// The theme `user` chose for boards, or else `guild`'s default
+fn board_theme(guild: GuildId, user: UserId) -> &'static Theme {
+	Theme::by_name(&CONFIG.user_setting(user, "theme"))
+		.or_else(|| Theme::by_name(&CONFIG.guild_setting(guild, "theme")))
+		.unwrap_or(&THEMES[0])
+}
+
+/// How `user` has asked for boards to be drawn, in the size and format `guild` sends them
+fn board_options(guild: GuildId, user: UserId) -> RenderOptions {
+	RenderOptions {
+		theme: board_theme(guild, user).name.to_owned(),
+		pieces: CONFIG.user_setting(user, "pieces"),
+		square_size: CONFIG.guild_setting(guild, "boardSize").parse().unwrap_or(SQUARE_SIZE),
+		format: ImageFormat::from_name(&CONFIG.guild_setting(guild, "imageFormat")).unwrap_or(ImageFormat::Png),
+		..RenderOptions::default()
+	}
+}
+
+#[inline]
+fn guild_rating_system(guild: GuildId) -> RatingSystem {
+	RatingSystem::from_setting(&CONFIG.guild_setting(guild, "ratingSystem"))
+}
+
+/// A player's rating in `guild` for display, marked with a ? while provisional
+fn rating_label(guild: GuildId, user: UserId) -> String {
+	let (rating, provisional) = RATINGS.get(&(guild, user)).map(|r| r.clone()).unwrap_or_default().display(guild_rating_system(guild));
+	format!("{:.0}{}", rating, if provisional { "?" } else { "" })
+}
+
+/// Recalculate every Glicko-2 rating at the end of a rating period
+fn close_rating_period() {
+	for (key, _) in RATINGS.clone() {
+		RATINGS.alter(key, |opt| opt.map(|mut ratings| {
+			ratings.glicko.close_period();
+			ratings
+		}));
+	}
+}
+
 fn check_perm(msg: &Message, perm: &str) -> CommandResult {
 	CONFIG.lazy_guild(msg.guild_id.unwrap());
 	match CONFIG.guild_settings.read().unwrap().get(&msg.guild_id.unwrap()).unwrap().get_perm(perm.to_string(), msg.author.id, msg.channel_id) {
@@ -400,17 +629,32 @@ fn play(ctx: &mut Context, msg: &Message) -> CommandResult {
 		if let Ok(plb) = args.single::<String>() {
 			let idx = if plb.as_bytes()[2] == 33 { 3 } else { 2 };
 			let plb = UserId::from(plb[idx..plb.len()-1].parse::<u64>()?);
+			let mut clock = None;
+			let mut rated = true;
+			while let Ok(arg) = args.single::<String>() {
+				if arg == "casual" {
+					rated = false;
+				} else if let Some(c) = Clock::parse(&arg) {
+					clock = Some(c);
+				} else {
+					msg.reply(ctx, format!("I don't understand `{}` (try `c>play @someone 5+3 casual`)", arg))?;
+					return Ok(());
+				}
+			}
 			let worb = random::<bool>();
 			*gm = ChannelGame {
 				white: if worb { pla } else { plb },
 				black: if worb { plb } else { pla },
 				initiator: if worb { Color::White } else { Color::Black },
 				state: ChannelGameState::Requested,
+				guild: msg.guild_id.unwrap(),
+				rated,
+				clock,
 				..ChannelGame::new()
 			};
 			msg.reply(ctx, format!("I've set up your game. You're playing as {}", if worb { "White" } else { "Black" }))?;
 		} else {
-			msg.reply(ctx, "Who are you playing against? (`c>play @someone`)")?;
+			msg.reply(ctx, "Who are you playing against? (`c>play @someone [minutes+increment] [casual]`)")?;
 		}
 	}
 
@@ -423,7 +667,10 @@ fn accept(ctx: &mut Context, msg: &Message) -> CommandResult {
 
 		if gm.state == ChannelGameState::Requested && gm.get_other() == msg.author.id {
 			gm.state = ChannelGameState::Running;
-			post_board(ctx, &mut gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
+			if let Some(clock) = &mut gm.clock {
+				clock.start();
+			}
+			post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
 		}
 	} else {
 		msg.reply(ctx, "You haven't been asked to play")?;
@@ -460,33 +707,104 @@ fn cancel(ctx: &mut Context, msg: &Message) -> CommandResult {
 	Ok(())
 }
 
+/// The users mentioned in `content` in order, or None if a mention's id can't be a user's
+fn mentions(content: &str) -> Option<Vec<UserId>> {
+	USER_PING.captures_iter(content).map(|caps| caps[1].parse::<u64>().ok().map(UserId)).collect()
+}
+
 #[command]
 #[aliases("stats", "stat")]
 fn statistics(ctx: &mut Context, msg: &Message) -> CommandResult {
-	USERS.alter(msg.author.id, |opt| match opt {
-		Some(stats) => Some(stats),
-		None=> Some(UserStats::default())
-	});
-	let stats = USERS.get(&msg.author.id).unwrap();
+	let mentioned = match mentions(&msg.content) {
+		Some(mentioned) => mentioned,
+		None => {
+			msg.reply(ctx, "Unknown user")?;
+			return Ok(());
+		}
+	};
+	if mentioned.len() >= 2 {
+		return head_to_head(ctx, msg, mentioned[0], mentioned[1]);
+	}
+
+	let user = mentioned.first().cloned().unwrap_or(msg.author.id);
+	let guild = msg.guild_id.unwrap();
+	let global = msg.content.split_whitespace().skip(1).any(|arg| arg == "global");
+	let stats = if global {
+		USERS.get(&user).map(|s| *s)
+	} else {
+		GUILD_USERS.get(&(guild, user)).map(|s| *s)
+	}.unwrap_or_default();
+	let achievements = ACHIEVEMENTS.get(&user).map(|a| a.clone()).unwrap_or_default();
+	let openings: Vec<String> = opening_results(HISTORY.read()?.iter().filter(|r| global || r.guild == guild), user).into_iter()
+		.take(5)
+		.map(|(opening, tally)| format!("{}: +{} ={} -{}", opening, tally[0], tally[1], tally[2]))
+		.collect();
+	let name = user.to_user(&ctx)?.name;
 
 	msg.channel(&ctx).unwrap().guild().unwrap().read().send_message(&ctx, |m| m.embed(|embed| {
 		embed.colour(serenity::utils::Colour::from_rgb(255, 255, 0));
-		embed.field("Games", format!("Total: {}", stats.won_checkmate + stats.won_default + stats.drawn_stalemate + stats.drawn_agreement + stats.drawn_declared + stats.lost_checkmate + stats.lost_resigned), false);
+		embed.title(format!("Statistics for {}", name));
+		embed.description(if global { "Games in all servers" } else { "Games in this server (`global` for all servers)" });
+		embed.field("Rating", match guild_rating_system(guild) {
+			RatingSystem::Elo => format!("{} in this server", rating_label(guild, user)),
+			RatingSystem::Glicko2 => {
+				let glicko = RATINGS.get(&(guild, user)).map(|r| r.glicko.preview()).unwrap_or_default();
+				format!("{} in this server\nDeviation: {:.0}\nVolatility: {:.3}", rating_label(guild, user), glicko.deviation, glicko.volatility)
+			}
+		}, false);
+		embed.field("Games", format!("Total: {}\nAborted: {}", stats.games(), stats.aborted), false);
+		embed.field(
+			"As White",
+			format!("Won: {}\nDrawn: {}\nLost: {}", stats.as_white[0], stats.as_white[1], stats.as_white[2]),
+			true
+		);
+		embed.field(
+			"As Black",
+			format!("Won: {}\nDrawn: {}\nLost: {}", stats.as_black[0], stats.as_black[1], stats.as_black[2]),
+			true
+		);
+		embed.field(
+			"Streaks",
+			format!(
+				"Current: {}\nBest: {}\nAverage game: {}",
+				stats.current_streak, stats.best_streak,
+				stats.average_length().map(|moves| format!("{:.1} moves", moves)).unwrap_or_else(|| "-".to_owned())
+			),
+			true
+		);
 		embed.field(
 			"Games won",
-			format!("In total: {}\nBy checkmate: {}\nBy default: {}", stats.won_checkmate + stats.won_default, stats.won_checkmate, stats.won_default),
+			format!(
+				"In total: {}\nBy checkmate: {}\nBy resignation: {}\nOn time: {}\nBy abandonment: {}\nBy adjudication: {}",
+				stats.won(), stats.won_checkmate, stats.won_default, stats.won_timeout, stats.won_abandoned, stats.won_adjudicated
+			),
 			true
 		);
 		embed.field(
 			"Games drawn",
-			format!("In total: {}\nBy stalemate: {}\nBy agreement: {}\nBy declaration: {}", stats.drawn_agreement + stats.drawn_declared + stats.drawn_stalemate, stats.drawn_stalemate, stats.drawn_agreement, stats.drawn_declared),
+			format!(
+				"In total: {}\nBy stalemate: {}\nBy agreement: {}\nBy repetition: {}\nBy fifty-move rule: {}\nBy insufficient material: {}\nBy fivefold repetition: {}\nBy seventy-five-move rule: {}\nBy adjudication: {}",
+				stats.drawn(), stats.drawn_stalemate, stats.drawn_agreement, stats.drawn_repetition, stats.drawn_fifty_move,
+				stats.drawn_insufficient, stats.drawn_fivefold, stats.drawn_seventy_five_move, stats.drawn_adjudicated
+			),
 			true
 		);
 		embed.field(
 			"Games lost",
-			format!("In total: {}\nBy checkmate: {}\nBy resignation: {}", stats.lost_checkmate + stats.lost_resigned, stats.lost_checkmate, stats.lost_resigned),
+			format!(
+				"In total: {}\nBy checkmate: {}\nBy resignation: {}\nOn time: {}\nBy abandonment: {}\nBy adjudication: {}",
+				stats.lost(), stats.lost_checkmate, stats.lost_resigned, stats.lost_timeout, stats.lost_abandoned, stats.lost_adjudicated
+			),
 			true
 		);
+		if !openings.is_empty() {
+			embed.field("Most played openings", openings.join("\n"), false);
+		}
+		embed.field("Achievements", if achievements.is_empty() {
+			"None yet".to_owned()
+		} else {
+			achievements.iter().map(|a| format!("🏅 **{}**: {}", a.name(), a.description())).collect::<Vec<_>>().join("\n")
+		}, false);
 		embed.field("Actions", "_ _", false);
 		embed.field(
 			"Moves made",
@@ -503,6 +821,61 @@ fn statistics(ctx: &mut Context, msg: &Message) -> CommandResult {
 			stats.checks_given,
 			true
 		);
+		embed.field(
+			"Castles",
+			stats.castles,
+			true
+		);
+		embed.field(
+			"Promotions",
+			stats.promotions,
+			true
+		);
+		embed.field(
+			"En passant captures",
+			stats.en_passant_captures,
+			true
+		);
+		embed
+	}))?;
+
+	Ok(())
+}
+
+fn head_to_head(ctx: &Context, msg: &Message, a: UserId, b: UserId) -> CommandResult {
+	const RECENT_GAMES: usize = 5;
+
+	let history = HISTORY.read()?;
+	let h2h = HeadToHead::between(history.iter(), a, b);
+	let recent: Vec<String> = history.iter().rev()
+		.filter(|r| r.is_between(a, b) && r.termination.white_score().is_some()) // Leaving out aborted games
+		.take(RECENT_GAMES)
+		.map(|r| format!("`{}` <@{}> vs <@{}>: {}", r.finished.format("%Y-%m-%d"), r.white, r.black, r.termination.description()))
+		.collect();
+	std::mem::drop(history);
+
+	let total = |tally: &[u32; 3]| tally.iter().sum::<u32>();
+	if total(&h2h.as_white) + total(&h2h.as_black) == 0 {
+		msg.reply(ctx, format!("<@{}> and <@{}> haven't finished a game against each other", a, b))?;
+		return Ok(());
+	}
+
+	msg.channel_id.send_message(ctx, |m| m.embed(|embed| {
+		embed.colour(serenity::utils::Colour::from_rgb(255, 255, 0));
+		embed.description(format!("<@{}> against <@{}>", a, b));
+		embed.field(
+			"In total",
+			format!("Won: {}\nDrawn: {}\nLost: {}", h2h.as_white[0] + h2h.as_black[0], h2h.as_white[1] + h2h.as_black[1], h2h.as_white[2] + h2h.as_black[2]),
+			true
+		);
+		embed.field("As White", format!("Won: {}\nDrawn: {}\nLost: {}", h2h.as_white[0], h2h.as_white[1], h2h.as_white[2]), true);
+		embed.field("As Black", format!("Won: {}\nDrawn: {}\nLost: {}", h2h.as_black[0], h2h.as_black[1], h2h.as_black[2]), true);
+		if let Some((user, length)) = h2h.longest_streak {
+			embed.field("Longest streak", format!("<@{}> won {} in a row", user, length), false);
+		}
+		if !recent.is_empty() {
+			embed.field("Most recent games", recent.join("\n"), false);
+		}
 		embed
 	}))?;
 
@@ -510,30 +883,371 @@ fn statistics(ctx: &mut Context, msg: &Message) -> CommandResult {
 }
 
 #[command]
-fn board(ctx: &mut Context, msg: &Message) -> CommandResult {
-	if let Some(gm) = GAMES.get_mut(&msg.channel_id) {
-		if gm.state != ChannelGameState::Running {
-			post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
+#[aliases("lb", "top")]
+fn leaderboard(ctx: &mut Context, msg: &Message) -> CommandResult {
+	const PAGE_SIZE: usize = 10;
+
+	let guild = msg.guild_id.unwrap();
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+	let mut category = "rating".to_owned();
+	let mut page = 1;
+	let mut image = false;
+	while let Ok(arg) = args.single::<String>() {
+		match &*arg {
+			"rating" | "wins" | "games" => category = arg,
+			"image" => image = true,
+			_ => match arg.parse::<usize>() {
+				Ok(n) if n > 0 => page = n,
+				_ => {
+					msg.reply(ctx, "Which leaderboard? (`c>leaderboard [rating|wins|games] [page] [image]`)")?;
+					return Ok(());
+				}
+			}
 		}
+	}
+
+	let mut entries: Vec<(UserId, f64, String)> = if category == "rating" {
+		let system = guild_rating_system(guild);
+		RATINGS.clone().into_iter().filter(|((g, _), _)| *g == guild).map(|((_, user), ratings)| {
+			let (rating, provisional) = ratings.display(system);
+			(user, rating, format!("{:.0}{}", rating, if provisional { "?" } else { "" }))
+		}).collect()
 	} else {
-		msg.reply(ctx, "There is no game running")?;
+		GUILD_USERS.clone().into_iter().filter(|((g, _), _)| *g == guild).map(|((_, user), stats)| {
+			let value = if category == "wins" { stats.won() } else { stats.games() };
+			(user, value as f64, value.to_string())
+		}).filter(|(_, value, _)| *value > 0.0).collect()
+	};
+	entries.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+	if entries.is_empty() {
+		msg.reply(ctx, "Nobody has played here yet")?;
+		return Ok(());
+	}
+	let pages = entries.len().div_ceil(PAGE_SIZE);
+	if page > pages {
+		msg.reply(ctx, format!("There {} only {} page{}", if pages == 1 { "is" } else { "are" }, pages, if pages == 1 { "" } else { "s" }))?;
+		return Ok(());
+	}
+	let first = (page - 1) * PAGE_SIZE;
+	let shown = &entries[first..(first + PAGE_SIZE).min(entries.len())];
+
+	let lines: String = shown.iter().enumerate().map(|(i, (user, _, label))| format!("{}. <@{}> — {}\n", first + i + 1, user, label)).collect();
+	let png = if image {
+		let mut rows = Vec::new();
+		for (i, (user, value, label)) in shown.iter().enumerate() {
+			rows.push((first + i + 1, user.to_user(&ctx)?.name, *value, label.clone()));
+		}
+		Some(encode_png(&leaderboard_image(&rows, entries[0].1)))
+	} else {
+		None
+	};
+
+	msg.channel_id.send_message(&ctx, |m| {
+		m.embed(|embed| {
+			embed.colour(serenity::utils::Colour::from_rgb(255, 255, 0));
+			embed.title(match &*category { "rating" => "Highest rated", "wins" => "Most wins", _ => "Most games" });
+			embed.description(lines);
+			embed.footer(|f| f.text(format!("Page {} of {}", page, pages)));
+			if png.is_some() {
+				embed.image("attachment://leaderboard.png");
+			}
+			embed
+		});
+		if let Some(png) = &png {
+			m.add_file(AttachmentType::Bytes { data: Cow::from(png), filename: String::from("leaderboard.png") });
+		}
+		m
+	})?;
+
+	Ok(())
+}
+
+#[command]
+fn games(ctx: &mut Context, msg: &Message) -> CommandResult {
+	const RECENT_GAMES: usize = 10;
+
+	let guild = msg.guild_id.unwrap();
+	let user = match mentions(&msg.content) {
+		Some(mentioned) => mentioned.first().cloned().unwrap_or(msg.author.id),
+		None => {
+			msg.reply(ctx, "Unknown user")?;
+			return Ok(());
+		}
+	};
+	let history = HISTORY.read()?;
+	let lines: Vec<String> = history.iter().enumerate().rev()
+		.filter(|(_, r)| r.guild == guild && (r.white == user || r.black == user))
+		.take(RECENT_GAMES)
+		.map(|(i, r)| {
+			let color = if r.white == user { Color::White } else { Color::Black };
+			let result = match r.termination.winner() {
+				_ if r.termination == Termination::Aborted => "Aborted",
+				Some(winner) if winner == color => "Won",
+				Some(_) => "Lost",
+				None => "Drawn",
+			};
+			format!(
+				"`#{}` `{}` {} as {} vs <@{}> — {}",
+				i + 1, r.finished.format("%Y-%m-%d"), result, color_name(color), r.player(!color), r.opening_label(6)
+			)
+		})
+		.collect();
+	std::mem::drop(history);
+
+	if lines.is_empty() {
+		msg.reply(ctx, format!("<@{}> hasn't finished a game in this server", user))?;
+		return Ok(());
+	}
+	msg.channel_id.send_message(ctx, |m| m.embed(|embed| {
+		embed.colour(serenity::utils::Colour::from_rgb(255, 255, 0));
+		embed.title("Recent games");
+		embed.description(format!("<@{}>\n{}", user, lines.join("\n")));
+		embed.footer(|f| f.text("Use c>game <id> for the PGN and final position"));
+		embed
+	}))?;
+
+	Ok(())
+}
+
+#[command]
+fn profile(ctx: &mut Context, msg: &Message) -> CommandResult {
+	const RECENT_GAMES: usize = 10;
+
+	let guild = msg.guild_id.unwrap();
+	let user = match mentions(&msg.content) {
+		Some(mentioned) => mentioned.first().cloned().unwrap_or(msg.author.id),
+		None => {
+			msg.reply(ctx, "Unknown user")?;
+			return Ok(());
+		}
+	};
+	let stats = GUILD_USERS.get(&(guild, user)).map(|s| *s).unwrap_or_default();
+
+	let history = HISTORY.read()?;
+	let played: Vec<&GameRecord> = history.iter().filter(|r| r.guild == guild).collect();
+	let recent: Vec<char> = played.iter().rev()
+		.filter(|r| r.white == user || r.black == user)
+		.filter_map(|r| r.result_for(user))
+		.take(RECENT_GAMES)
+		.map(|result| ['W', 'D', 'L'][result])
+		.collect();
+	let favourite = opening_results(played.into_iter(), user).into_iter().next().map(|(opening, _)| opening);
+	std::mem::drop(history);
+
+	let name = user.to_user(&ctx)?.name;
+	let png = encode_png(&profile_image(user, &name, &rating_label(guild, user), &stats, favourite.as_deref(), &recent));
+	msg.channel_id.send_message(&ctx, |m| {
+		m.add_file(AttachmentType::Bytes { data: Cow::from(&png), filename: String::from("profile.png") });
+		m
+	})?;
+
+	Ok(())
+}
+
+#[command]
+fn game(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+	let id = match args.single::<String>().ok().and_then(|id| id.trim_start_matches('#').parse::<usize>().ok()) {
+		Some(id) => id,
+		None => {
+			msg.reply(ctx, "Which game? (`c>game <id>`, ids are listed by `c>games`)")?;
+			return Ok(());
+		}
+	};
+
+	let history = HISTORY.read()?;
+	let record = match history.get(id.wrapping_sub(1)) {
+		Some(record) if record.guild == msg.guild_id.unwrap() => record,
+		_ => {
+			msg.reply(ctx, format!("There is no game #{} in this server", id))?;
+			return Ok(());
+		}
+	};
+	let (white, black) = (record.white.to_user(&ctx)?, record.black.to_user(&ctx)?);
+	let pgn = record.to_pgn(&white.tag(), &black.tag());
+	let own = [Color::White, Color::Black].iter().cloned().find(|&color| record.player(color) == msg.author.id);
+	let position = record.final_position();
+	let options = RenderOptions {
+		orientation: Orientation::from_setting(&CONFIG.user_setting(msg.author.id, "orientation")).side(own, position.side_to_move()),
+		last_move: record.moves.last().cloned(),
+		panel: if CONFIG.user_flag(msg.author.id, "sidePanel") {
+			Some(Panel { names: [white.name.clone(), black.name.clone()], moves: record.moves.clone() })
+		} else {
+			None
+		},
+		..board_options(record.guild, msg.author.id)
+	};
+	let image = RENDERER.render_file(&position, &options);
+	let content = format!(
+		"Game #{}: **{}** vs **{}**{}, {} on {}",
+		id, white.name, black.name, if record.rated { "" } else { " (casual)" },
+		record.termination.description(), record.finished.format("%Y-%m-%d")
+	);
+	std::mem::drop(history);
+
+	msg.channel_id.send_message(ctx, |m| {
+		m.content(content);
+		m.add_file(AttachmentType::Bytes { data: Cow::from(&image[..]), filename: format!("board.{}", options.format.extension()) });
+		m.add_file(AttachmentType::Bytes { data: Cow::from(pgn.as_bytes()), filename: String::from("game.pgn") });
+		m
+	})?;
+
+	Ok(())
+}
+
+/// Frame delay for replays when none is given, and the range allowed, in hundredths of a second
+const REPLAY_DELAY: u16 = 100;
+const REPLAY_DELAY_RANGE: (u16, u16) = (10, 500);
+
+#[command]
+fn replay(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+	let mut id = None;
+	let mut delay = REPLAY_DELAY;
+	for arg in args.iter::<String>().filter_map(Result::ok) {
+		let seconds = if let Some(ms) = arg.strip_suffix("ms") {
+			ms.parse::<f64>().ok().map(|ms| ms / 1000.0)
+		} else {
+			arg.strip_suffix('s').and_then(|s| s.parse::<f64>().ok())
+		};
+		match (seconds, arg.trim_start_matches('#').parse::<usize>()) {
+			(Some(seconds), _) => delay = ((seconds * 100.0).round() as u16).max(REPLAY_DELAY_RANGE.0).min(REPLAY_DELAY_RANGE.1),
+			(None, Ok(number)) => id = Some(number),
+			_ => {
+				msg.reply(ctx, "Usage: `c>replay [game id] [delay]`, with the delay like `500ms` or `1.5s`")?;
+				return Ok(());
+			}
+		}
+	}
+
+	// The game that just finished in this channel, or one from the history
+	let (white, black, moves) = match id {
+		None => match GAMES.get(&msg.channel_id) {
+			Some(gm) if gm.termination.is_some() => (gm.white, gm.black, gm.moves()),
+			_ => {
+				msg.reply(ctx, "No game has finished in this channel since the last one started; give an id from `c>games`")?;
+				return Ok(());
+			}
+		},
+		Some(id) => match HISTORY.read()?.get(id.wrapping_sub(1)) {
+			Some(record) if record.guild == msg.guild_id.unwrap() => (record.white, record.black, record.moves.clone()),
+			_ => {
+				msg.reply(ctx, format!("There is no game #{} in this server", id))?;
+				return Ok(());
+			}
+		},
+	};
+
+	msg.channel_id.broadcast_typing(&ctx)?;
+	let names = [white.to_user(&ctx)?.name, black.to_user(&ctx)?.name];
+	let own = [(Color::White, white), (Color::Black, black)].iter().find(|&&(_, player)| player == msg.author.id).map(|&(color, _)| color);
+	let options = RenderOptions {
+		// A replay can't follow the side to move, so it's drawn as for the starting position
+		orientation: Orientation::from_setting(&CONFIG.user_setting(msg.author.id, "orientation")).side(own, Color::White),
+		panel: if CONFIG.user_flag(msg.author.id, "sidePanel") { Some(Panel { names: names.clone(), moves: Vec::new() }) } else { None },
+		..board_options(msg.guild_id.unwrap(), msg.author.id)
+	};
+	let gif = RENDERER.render_replay(&moves, &options, delay);
+	msg.channel_id.send_message(ctx, |m| {
+		m.content(format!("**{}** vs **{}**, {} moves", names[0], names[1], moves.len().div_ceil(2)));
+		m.add_file(AttachmentType::Bytes { data: Cow::from(&gif[..]), filename: String::from("replay.gif") });
+		m
+	})?;
+
+	Ok(())
+}
+
+#[command]
+fn board(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+
+	match GAMES.get(&msg.channel_id) {
+		Some(gm) if gm.state == ChannelGameState::Running || gm.termination.is_some() => {
+			// Drawn for whoever asked, so spectators can look from either side without changing the players' boards
+			let preferred = board_orientation(&gm, msg.author.id);
+			let orientation = match args.single::<String>().ok().as_deref() {
+				None => preferred,
+				Some("flip") => !preferred,
+				Some("white") => Color::White,
+				Some("black") => Color::Black,
+				Some(_) => {
+					msg.reply(ctx, "Usage: `c>board [flip|white|black]`")?;
+					return Ok(());
+				}
+			};
+			post_requested_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read(), msg.author.id, orientation)?;
+		}
+		_ => {
+			msg.reply(ctx, "There is no game running")?;
+		}
 	}
 
 	Ok(())
 }
 
+#[command]
+fn diagram(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+
+	let mut options = board_options(msg.guild_id.unwrap(), msg.author.id);
+	let mut fen = Vec::new();
+	for token in args.iter::<String>().filter_map(Result::ok) {
+		if let Some(arrow) = parse_arrow(&token) {
+			options.arrows.push(arrow);
+		} else if let Some(mark) = parse_mark(&token) {
+			options.marks.push(mark);
+		} else if token.starts_with('@') {
+			msg.reply(ctx, format!("`{}` isn't a square to mark (like `@d5` or `@d5red`)", token))?;
+			return Ok(());
+		} else {
+			fen.push(token);
+		}
+	}
+
+	let (position, own) = if !fen.is_empty() {
+		match parse_fen(&fen.join(" ")) {
+			Some(board) => (board, None),
+			None => {
+				msg.reply(ctx, "That isn't a legal position. Arrows are written like `e2e4` or `e2e4red`, marks like `@d5` or `@d5red`")?;
+				return Ok(());
+			}
+		}
+	} else if let Some(gm) = GAMES.get(&msg.channel_id) {
+		(gm.game.current_position(), gm.player_color(msg.author.id))
+	} else {
+		(Board::default(), None)
+	};
+	options.orientation = Orientation::from_setting(&CONFIG.user_setting(msg.author.id, "orientation")).side(own, position.side_to_move());
+
+	let image = RENDERER.render_file(&position, &options);
+	msg.channel_id.send_message(ctx, |m| {
+		m.add_file(AttachmentType::Bytes { data: Cow::from(&image[..]), filename: format!("diagram.{}", options.format.extension()) });
+		m
+	})?;
+
+	Ok(())
+}
+
 #[command]
 fn resign(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+
 	if let Some(mut gm) = GAMES.get_mut(&msg.channel_id) {
 		if gm.state == ChannelGameState::Running {
-			if msg.author.id == gm.white {
-				gm.game.resign(Color::White);
-				post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
-				check_game_result(&mut gm);
-			} else if msg.author.id == gm.black {
-				gm.game.resign(Color::Black);
-				post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
-				check_game_result(&mut gm);
+			if let Some(color) = gm.player_color(msg.author.id) {
+				let confirmed = args.rest() == "confirm" && gm.take_confirmation(color, Confirmable::Resign);
+				if confirmed || !CONFIG.user_flag(msg.author.id, "confirmResign") {
+					resign_game(ctx, &mut gm, color, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
+				} else {
+					request_confirmation(ctx, msg, &mut gm, color, Confirmable::Resign)?;
+				}
 			} else {
 				msg.reply(ctx, "You're not playing this game")?;
 			}
@@ -547,34 +1261,32 @@ fn resign(ctx: &mut Context, msg: &Message) -> CommandResult {
 
 #[command]
 fn draw(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+
 	if let Some(mut gm) = GAMES.get_mut(&msg.channel_id) {
 		if gm.state == ChannelGameState::Running {
-			if msg.author.id == gm.white {
-				if gm.game.side_to_move() == Color::White && gm.game.can_declare_draw() {
+			if let Some(color) = gm.player_color(msg.author.id) {
+				let claim = if gm.game.side_to_move() == color { gm.claimable_draw() } else { None };
+				if let Some(claim) = claim {
 					gm.game.declare_draw();
-					post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
+					gm.termination = Some(Termination::Claimed(claim));
 					check_game_result(&mut gm);
-				} else if gm.draw_offer == Some(Color::Black) {
-					gm.game.accept_draw();
 					post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
-					check_game_result(&mut gm);
-				} else {
-					gm.draw_offer = Some(Color::White);
-					gm.game.offer_draw(Color::White);
-					msg.reply(ctx, "You have offered a draw")?;
-				}
-			} else if msg.author.id == gm.black {
-				if gm.game.side_to_move() == Color::Black && gm.game.can_declare_draw() {
-					gm.game.declare_draw();
-					post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
-				} else if gm.draw_offer == Some(Color::White) {
-					gm.game.accept_draw();
-					post_board(ctx, &gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
-					check_game_result(&mut gm);
+				} else if gm.draw_offer == Some(!color) {
+					let confirmed = args.rest() == "confirm" && gm.take_confirmation(color, Confirmable::AcceptDraw);
+					if confirmed || !CONFIG.user_flag(msg.author.id, "confirmDraw") {
+						accept_draw(ctx, &mut gm, &msg.channel(&ctx).unwrap().guild().unwrap().read())?;
+					} else {
+						request_confirmation(ctx, msg, &mut gm, color, Confirmable::AcceptDraw)?;
+					}
+				} else if gm.draw_offer == Some(color) {
+					msg.reply(ctx, "You have already offered a draw")?;
 				} else {
-					gm.draw_offer = Some(Color::Black);
-					gm.game.offer_draw(Color::White);
-					msg.reply(ctx, "You have offered a draw")?;
+					gm.draw_offer = Some(color);
+					gm.game.offer_draw(color);
+					let opponent = gm.player(!color);
+					msg.reply(ctx, format!("You have offered a draw. <@{}>, use `c>draw` to accept or `c>decline-draw` to decline", opponent))?;
 				}
 			} else {
 				msg.reply(ctx, "You're not playing this game")?;
@@ -587,6 +1299,96 @@ fn draw(ctx: &mut Context, msg: &Message) -> CommandResult {
 	Ok(())
 }
 
+#[command("decline-draw")]
+fn decline_draw(ctx: &mut Context, msg: &Message) -> CommandResult {
+	if let Some(mut gm) = GAMES.get_mut(&msg.channel_id) {
+		if gm.state == ChannelGameState::Running {
+			match gm.player_color(msg.author.id) {
+				Some(color) if gm.draw_offer == Some(!color) => {
+					gm.draw_offer = None;
+					msg.reply(ctx, "You have declined the draw offer")?;
+				}
+				Some(_) => { msg.reply(ctx, "There is no draw offer to decline")?; }
+				None => { msg.reply(ctx, "You're not playing this game")?; }
+			}
+		}
+	} else {
+		msg.reply(ctx, "There is no game running")?;
+	}
+
+	Ok(())
+}
+
+#[command]
+fn premove(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+	let text = args.single::<String>().unwrap_or_default();
+
+	if let Some(mut gm) = GAMES.get_mut(&msg.channel_id) {
+		if gm.state == ChannelGameState::Running {
+			match gm.player_color(msg.author.id) {
+				Some(color) if gm.game.side_to_move() == color => { msg.reply(ctx, "It's your move, so just play it")?; }
+				Some(_) if text == "clear" => {
+					gm.premoves.clear();
+					msg.reply(ctx, "Your queued moves have been cleared")?;
+				}
+				Some(_) if is_move_text(&text) => {
+					gm.premoves.retain(|p| p.condition.is_some());
+					gm.premoves.push(Premove { condition: None, reply: text.clone() });
+					msg.reply(ctx, format!("I'll play {} after your opponent moves, if it's legal then", text))?;
+				}
+				Some(_) => { msg.reply(ctx, "Which move? (`c>premove Nf3`, or `c>premove clear`)")?; }
+				None => { msg.reply(ctx, "You're not playing this game")?; }
+			}
+		}
+	} else {
+		msg.reply(ctx, "There is no game running")?;
+	}
+
+	Ok(())
+}
+
+#[command("if")]
+fn conditional(ctx: &mut Context, msg: &Message) -> CommandResult {
+	let mut args = Args::new(&msg.content, &[Delimiter::Single(' ')]);
+	args.advance();
+	let condition = args.single::<String>().unwrap_or_default();
+	let then = args.single::<String>().unwrap_or_default();
+	let reply = args.single::<String>().unwrap_or_default();
+	if then != "then" || !is_move_text(&condition) || !is_move_text(&reply) {
+		msg.reply(ctx, "Which moves? (`c>if e5 then Nf3`)")?;
+		return Ok(());
+	}
+
+	if let Some(mut gm) = GAMES.get_mut(&msg.channel_id) {
+		if gm.state == ChannelGameState::Running {
+			match gm.player_color(msg.author.id) {
+				Some(color) if gm.game.side_to_move() == color => { msg.reply(ctx, "It's your move, so just play it")?; }
+				Some(_) => {
+					let position = gm.game.current_position();
+					match <ChessMove as FromSan>::from_san(&position, &condition) {
+						Ok(mv) => match <ChessMove as FromSan>::from_san(&position.make_move_new(mv), &reply) {
+							Ok(_) => {
+								gm.premoves.retain(|p| p.condition.as_ref() != Some(&condition));
+								gm.premoves.push(Premove { condition: Some(condition.clone()), reply: reply.clone() });
+								msg.reply(ctx, format!("I'll play {} if your opponent plays {}", reply, condition))?;
+							}
+							Err(_) => { msg.reply(ctx, format!("{} wouldn't be legal after {}", reply, condition))?; }
+						},
+						Err(_) => { msg.reply(ctx, format!("Your opponent can't play {}", condition))?; }
+					}
+				}
+				None => { msg.reply(ctx, "You're not playing this game")?; }
+			}
+		}
+	} else {
+		msg.reply(ctx, "There is no game running")?;
+	}
+
+	Ok(())
+}
+
 #[command]
 fn enable(ctx: &mut Context, msg: &Message) -> CommandResult {
 	CONFIG.lazy_guild(msg.guild_id.unwrap());
@@ -615,6 +1417,15 @@ fn config(ctx: &mut Context, msg: &Message) -> CommandResult {
 	args.advance();
 	let setting = args.single::<String>()?;
 	if let Ok(val) = args.single::<String>() {
+		let invalid = match &*setting {
+			"boardSize" if !matches!(val.parse::<i32>(), Ok(size) if SQUARE_SIZES.contains(&size)) => Some("boardSize must be 40, 60, 80 or 120"),
+			"imageFormat" if ImageFormat::from_name(&val).is_none() => Some("imageFormat must be png, palette, jpeg or webp"),
+			_ => None,
+		};
+		if let Some(error) = invalid {
+			msg.reply(ctx, error)?;
+			return Ok(());
+		}
 		let old_value = settings.settings.insert(setting.clone(), val).unwrap_or_else(String::new);
 		msg.reply(ctx, format!("Value of {} set to \"{}\" (previously \"{}\")", setting, settings.settings.get(&setting).unwrap(), old_value))?;
 	} else {
@@ -645,7 +1456,7 @@ fn permissions(ctx: &mut Context, msg: &Message) -> CommandResult {
 	if mode == "set" {
 		let val = args.single::<bool>()?;
 		settings.set_perm(setting.clone(), val);
-		msg.reply(ctx, format!("Value of {} set to \"{}\"", setting, val.to_string()))?;
+		msg.reply(ctx, format!("Value of {} set to \"{}\"", setting, val))?;
 	} else {
 		if mode == "reset" {
 			settings.unset_perm(setting.clone());
@@ -653,11 +1464,11 @@ fn permissions(ctx: &mut Context, msg: &Message) -> CommandResult {
 		let opt = settings.get_perm(
 			setting.clone(),
 			match USER_PING.captures(&setting) {
-				Some(caps) => { u64::from_str_radix(caps.get(1).unwrap().as_str(), 10).unwrap().into() }
+				Some(caps) => { caps.get(1).unwrap().as_str().parse::<u64>().unwrap().into() }
 				None => msg.author.id
 			},
 			match CHANNEL_REF.captures(&setting) {
-				Some(caps) => { u64::from_str_radix(caps.get(1).unwrap().as_str(), 10).unwrap().into() }
+				Some(caps) => { caps.get(1).unwrap().as_str().parse::<u64>().unwrap().into() }
 				None => msg.channel_id
 			});
 		match opt {
@@ -667,7 +1478,7 @@ fn permissions(ctx: &mut Context, msg: &Message) -> CommandResult {
 				k.push_str(&msg.author.id.to_string());
 				k.push_str(".#");
 				k.push_str(&msg.channel_id.to_string());
-				msg.reply(ctx, format!("Value of {} is: \"{}\"{}", k, perm.0.to_string(), if perm.1 != k { format!(" (Inherited from {})", perm.1) } else { "".to_string() }))?;
+				msg.reply(ctx, format!("Value of {} is: \"{}\"{}", k, perm.0, if perm.1 != k { format!(" (Inherited from {})", perm.1) } else { "".to_string() }))?;
 			}
 			None => {
 				msg.reply(ctx, format!("Value of {} is unspecified", setting))?;
@@ -0,0 +1,68 @@
+use chess::{Board, ChessMove};
+use std::collections::HashMap;
+
+use discord_chess::san::play_line;
+
+#[derive(Clone)]
+pub struct Opening {
+	pub eco: String,
+	pub name: String,
+}
+
+/// Named openings, keyed by the position their main line reaches so that transpositions are recognised
+pub struct OpeningBook {
+	positions: HashMap<u64, Opening>,
+}
+
+impl OpeningBook {
+	/// Read a table of `eco`, `name` and `pgn` columns separated by tabs, with a header row.
+	/// Where two lines reach the same position, the first one listed names it
+	pub fn load(path: &str) -> OpeningBook {
+		let table = std::fs::read_to_string(path).unwrap();
+		let mut positions = HashMap::new();
+		for (number, line) in table.lines().enumerate().skip(1).filter(|(_, line)| !line.trim().is_empty()) {
+			let columns: Vec<&str> = line.split('\t').collect();
+			if columns.len() != 3 {
+				panic!("{}:{}: expected 3 columns", path, number + 1);
+			}
+			let (board, _) = play_line(&Board::default(), columns[2]).unwrap_or_else(|e| panic!("{}:{}: {}", path, number + 1, e));
+			positions.entry(board.get_hash()).or_insert_with(|| Opening { eco: columns[0].to_owned(), name: columns[1].to_owned() });
+		}
+		OpeningBook { positions }
+	}
+
+	/// The most specific opening the game passed through, if any
+	pub fn classify(&self, moves: &[ChessMove]) -> Option<&Opening> {
+		let mut board = Board::default();
+		let mut opening = None;
+		for &mv in moves {
+			board = board.make_move_new(mv);
+			if let Some(found) = self.positions.get(&board.get_hash()) {
+				opening = Some(found);
+			}
+		}
+		opening
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn classify<'a>(book: &'a OpeningBook, moves: &str) -> Option<&'a str> {
+		let (_, moves) = play_line(&Board::default(), moves).unwrap_or_else(|e| panic!("{}", e));
+		book.classify(&moves).map(|opening| &*opening.eco)
+	}
+
+	#[test]
+	fn longest_match_names_the_opening() {
+		let book = OpeningBook::load("res/openings.tsv");
+		assert_eq!(classify(&book, ""), None);
+		assert_eq!(classify(&book, "e4 e5"), Some("C20"));
+		assert_eq!(classify(&book, "e4 e5 Nf3"), Some("C40"));
+		assert_eq!(classify(&book, "e4 e5 Nf3 Nc6 Bb5"), Some("C60"));
+		assert_eq!(classify(&book, "e4 e5 Nf3 Nc6 Bb5 a6"), Some("C60")); // Past the end of the book's lines
+		assert_eq!(classify(&book, "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3 O-O c3 d5"), Some("C89"));
+		assert_eq!(classify(&book, "Nf3 d5 d4"), Some("D02")); // Transposes from 1. d4 d5 2. Nf3
+	}
+}
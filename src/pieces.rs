@@ -0,0 +1,87 @@
+use chess::{Color, Piece};
+use raster::Image;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const TILE_SIZE: i32 = 80;
+
+/// One image per piece and colour, cut from the images named in a set's `manifest.tsv`.
+/// Each manifest row gives a piece, its colour, an image in the set's directory and the top left corner
+/// of the piece's tile within that image, so a set can be a single sheet or one file per piece
+pub struct PieceSet {
+	pub name: String, // The directory's name
+	tiles: Vec<Image>, // Indexed by `Color::to_index` * 6 + `Piece::to_index`
+}
+
+impl PieceSet {
+	pub fn load(dir: &Path) -> PieceSet {
+		let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+		let manifest_path = dir.join("manifest.tsv");
+		let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+		let mut images: HashMap<String, Image> = HashMap::new();
+		let mut tiles: Vec<Option<Image>> = vec![None; 12];
+
+		for (number, line) in manifest.lines().enumerate().skip(1).filter(|(_, line)| !line.trim().is_empty()) {
+			let columns: Vec<&str> = line.split('\t').collect();
+			let fail = |reason: &str| -> ! { panic!("{}:{}: {}", manifest_path.display(), number + 1, reason) };
+			if columns.len() != 5 {
+				fail("expected 5 columns");
+			}
+			let piece = match columns[0] {
+				"pawn" => Piece::Pawn,
+				"knight" => Piece::Knight,
+				"bishop" => Piece::Bishop,
+				"rook" => Piece::Rook,
+				"queen" => Piece::Queen,
+				"king" => Piece::King,
+				_ => fail("unknown piece"),
+			};
+			let color = match columns[1] {
+				"white" => Color::White,
+				"black" => Color::Black,
+				_ => fail("unknown colour"),
+			};
+			let (x, y) = match (columns[3].parse::<i32>(), columns[4].parse::<i32>()) {
+				(Ok(x), Ok(y)) => (x, y),
+				_ => fail("bad tile position"),
+			};
+			let sheet = images.entry(columns[2].to_owned())
+				.or_insert_with(|| raster::open(dir.join(columns[2]).to_str().unwrap()).unwrap());
+			if x < 0 || y < 0 || x + TILE_SIZE > sheet.width || y + TILE_SIZE > sheet.height {
+				fail("tile lies outside the image");
+			}
+			tiles[color.to_index() * 6 + piece.to_index()] = Some(crop(sheet, x, y));
+		}
+
+		if tiles.iter().any(Option::is_none) {
+			panic!("{}: every piece needs an image in both colours", manifest_path.display());
+		}
+		PieceSet { name, tiles: tiles.into_iter().map(Option::unwrap).collect() }
+	}
+
+	/// Load every set in the subdirectories of `root`, with the default `classic` set first
+	pub fn load_all(root: &str) -> Vec<PieceSet> {
+		let mut sets: Vec<PieceSet> = std::fs::read_dir(root).unwrap()
+			.filter_map(|entry| entry.ok().map(|e| e.path()))
+			.filter(|path| path.is_dir())
+			.map(|path| PieceSet::load(&path))
+			.collect();
+		sets.sort_by_key(|set| (set.name != "classic", set.name.clone()));
+		sets
+	}
+
+	#[inline]
+	pub fn tile(&self, piece: Piece, color: Color) -> &Image {
+		&self.tiles[color.to_index() * 6 + piece.to_index()]
+	}
+}
+
+fn crop(sheet: &Image, x: i32, y: i32) -> Image {
+	let mut tile = Image::blank(TILE_SIZE, TILE_SIZE);
+	for row in 0..TILE_SIZE {
+		let src = (((y + row) * sheet.width + x) * 4) as usize;
+		let dst = (row * TILE_SIZE * 4) as usize;
+		tile.bytes[dst..dst + (TILE_SIZE * 4) as usize].copy_from_slice(&sheet.bytes[src..src + (TILE_SIZE * 4) as usize]);
+	}
+	tile
+}
@@ -0,0 +1,272 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+/// Elo players are provisional until they have finished this many rated games
+pub const PROVISIONAL_GAMES: u32 = 30;
+
+/// Glicko-2 players are provisional while their rating deviation is above this
+pub const PROVISIONAL_DEVIATION: f64 = 110.0;
+
+/// How often Glicko-2 ratings are recalculated from the games played since the last period
+pub const RATING_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+const GLICKO_SCALE: f64 = 173.7178;
+const GLICKO_TAU: f64 = 0.5; // Constrains how quickly volatility changes
+const GLICKO_EPSILON: f64 = 0.000_001;
+const MAX_DEVIATION: f64 = 350.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RatingSystem {
+	Elo,
+	Glicko2,
+}
+
+impl RatingSystem {
+	/// Read the `ratingSystem` guild setting, falling back to Elo
+	pub fn from_setting(setting: &str) -> RatingSystem {
+		match setting {
+			"glicko2" | "glicko" => RatingSystem::Glicko2,
+			_ => RatingSystem::Elo,
+		}
+	}
+}
+
+/// A player's ratings in one guild, kept under every system so the guild can switch between them
+#[derive(Clone, Default)]
+pub struct PlayerRatings {
+	pub elo: Elo,
+	pub glicko: Glicko2,
+}
+
+impl PlayerRatings {
+	/// Rate a game in which this player scored `score` against `opponent`, as rated before the game
+	pub fn record(&mut self, opponent: &PlayerRatings, score: f64) {
+		self.elo.update(&opponent.elo, score);
+		self.glicko.record(&opponent.glicko, score);
+	}
+
+	/// The rating shown under `system`, and whether it is still provisional
+	pub fn display(&self, system: RatingSystem) -> (f64, bool) {
+		match system {
+			RatingSystem::Elo => (self.elo.rating, self.elo.is_provisional()),
+			RatingSystem::Glicko2 => {
+				let current = self.glicko.preview();
+				(current.rating, current.is_provisional())
+			}
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+pub struct Elo {
+	pub rating: f64,
+	pub games: u32, // Rated games finished
+}
+
+impl Default for Elo {
+	fn default() -> Elo {
+		Elo { rating: 1500.0, games: 0 }
+	}
+}
+
+impl Elo {
+	#[inline]
+	pub fn is_provisional(&self) -> bool {
+		self.games < PROVISIONAL_GAMES
+	}
+
+	/// How quickly the rating moves: fast while provisional, slow at the top
+	pub fn k_factor(&self) -> f64 {
+		if self.is_provisional() {
+			40.0
+		} else if self.rating >= 2400.0 {
+			10.0
+		} else {
+			20.0
+		}
+	}
+
+	/// Expected score against `opponent`, between 0 and 1
+	pub fn expected(&self, opponent: &Elo) -> f64 {
+		1.0 / (1.0 + 10f64.powf((opponent.rating - self.rating) / 400.0))
+	}
+
+	/// Rate a game in which this player scored `score` (1 for a win, 0.5 for a draw, 0 for a loss)
+	/// against `opponent`, as rated before the game. Returns the change in rating
+	pub fn update(&mut self, opponent: &Elo, score: f64) -> f64 {
+		let change = self.k_factor() * (score - self.expected(opponent));
+		self.rating += change;
+		self.games += 1;
+		change
+	}
+}
+
+/// A game waiting to be rated at the end of the period
+#[derive(Clone, Copy)]
+pub struct GlickoResult {
+	pub opponent_rating: f64,
+	pub opponent_deviation: f64,
+	pub score: f64,
+}
+
+#[derive(Clone)]
+pub struct Glicko2 {
+	pub rating: f64,
+	pub deviation: f64,
+	pub volatility: f64,
+	pub pending: Vec<GlickoResult>, // Games played during the current period
+}
+
+impl Default for Glicko2 {
+	fn default() -> Glicko2 {
+		Glicko2 { rating: 1500.0, deviation: MAX_DEVIATION, volatility: 0.06, pending: Vec::new() }
+	}
+}
+
+impl Glicko2 {
+	#[inline]
+	pub fn is_provisional(&self) -> bool {
+		self.deviation > PROVISIONAL_DEVIATION
+	}
+
+	/// Remember a game against `opponent`, as rated before the game, for the end of the period
+	pub fn record(&mut self, opponent: &Glicko2, score: f64) {
+		self.pending.push(GlickoResult { opponent_rating: opponent.rating, opponent_deviation: opponent.deviation, score });
+	}
+
+	/// What this player's rating would be if the period ended now. Unlike closing the period,
+	/// this doesn't widen the deviation of players who haven't played
+	pub fn preview(&self) -> Glicko2 {
+		if self.pending.is_empty() {
+			self.clone()
+		} else {
+			self.rated()
+		}
+	}
+
+	pub fn close_period(&mut self) {
+		*self = self.rated();
+	}
+
+	/// The rating after the period's games, following Glickman's description of Glicko-2
+	fn rated(&self) -> Glicko2 {
+		let mu = (self.rating - 1500.0) / GLICKO_SCALE;
+		let phi = self.deviation / GLICKO_SCALE;
+		let sigma = self.volatility;
+
+		if self.pending.is_empty() {
+			// Deviation grows while a player is inactive
+			return Glicko2 {
+				deviation: ((phi * phi + sigma * sigma).sqrt() * GLICKO_SCALE).min(MAX_DEVIATION),
+				pending: Vec::new(),
+				..*self
+			};
+		}
+
+		let g = |phi_j: f64| 1.0 / (1.0 + 3.0 * phi_j * phi_j / (PI * PI)).sqrt();
+		let mut v_inv = 0.0;
+		let mut improvement = 0.0;
+		for result in &self.pending {
+			let mu_j = (result.opponent_rating - 1500.0) / GLICKO_SCALE;
+			let g_j = g(result.opponent_deviation / GLICKO_SCALE);
+			let expected = 1.0 / (1.0 + (-g_j * (mu - mu_j)).exp());
+			v_inv += g_j * g_j * expected * (1.0 - expected);
+			improvement += g_j * (result.score - expected);
+		}
+		let v = 1.0 / v_inv;
+		let delta = v * improvement;
+
+		// Find the new volatility with the Illinois algorithm
+		let a = (sigma * sigma).ln();
+		let f = |x: f64| {
+			let ex = x.exp();
+			ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2)) - (x - a) / (GLICKO_TAU * GLICKO_TAU)
+		};
+		let mut lower = a;
+		let mut upper = if delta * delta > phi * phi + v {
+			(delta * delta - phi * phi - v).ln()
+		} else {
+			let mut k = 1.0;
+			while f(a - k * GLICKO_TAU) < 0.0 {
+				k += 1.0;
+			}
+			a - k * GLICKO_TAU
+		};
+		let mut f_lower = f(lower);
+		let mut f_upper = f(upper);
+		while (upper - lower).abs() > GLICKO_EPSILON {
+			let c = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+			let f_c = f(c);
+			if f_c * f_upper <= 0.0 {
+				lower = upper;
+				f_lower = f_upper;
+			} else {
+				f_lower /= 2.0;
+			}
+			upper = c;
+			f_upper = f_c;
+		}
+		let new_sigma = (lower / 2.0).exp();
+
+		let phi_star = (phi * phi + new_sigma * new_sigma).sqrt();
+		let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+		let new_mu = mu + new_phi * new_phi * improvement;
+
+		Glicko2 {
+			rating: new_mu * GLICKO_SCALE + 1500.0,
+			deviation: (new_phi * GLICKO_SCALE).min(MAX_DEVIATION),
+			volatility: new_sigma,
+			pending: Vec::new(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn elo_provisional_k_factor() {
+		let opponent = Elo::default();
+		let mut player = Elo::default();
+		assert_eq!(player.update(&opponent, 1.0), 20.0); // K of 40 against an equal opponent
+		assert!(player.is_provisional());
+
+		let mut player = Elo { rating: 1500.0, games: PROVISIONAL_GAMES - 1 };
+		assert_eq!(player.k_factor(), 40.0);
+		player.update(&opponent, 0.5);
+		assert!(!player.is_provisional());
+		assert_eq!(player.k_factor(), 20.0);
+		assert_eq!(player.update(&opponent, 0.0), -10.0);
+
+		let master = Elo { rating: 2400.0, games: PROVISIONAL_GAMES };
+		assert_eq!(master.k_factor(), 10.0);
+		assert_eq!(Elo { games: 0, ..master }.k_factor(), 40.0);
+	}
+
+	#[test]
+	fn glicko2_worked_example() {
+		// The example in Glickman's "Example of the Glicko-2 system"
+		let mut player = Glicko2 { rating: 1500.0, deviation: 200.0, volatility: 0.06, pending: Vec::new() };
+		for &(rating, deviation, score) in &[(1400.0, 30.0, 1.0), (1550.0, 100.0, 0.0), (1700.0, 300.0, 0.0)] {
+			player.record(&Glicko2 { rating, deviation, ..Glicko2::default() }, score);
+		}
+		player.close_period();
+		assert!((player.rating - 1464.06).abs() < 0.01, "rating {}", player.rating);
+		assert!((player.deviation - 151.52).abs() < 0.01, "deviation {}", player.deviation);
+		assert!((player.volatility - 0.05999).abs() < 0.00001, "volatility {}", player.volatility);
+		assert!(player.pending.is_empty());
+	}
+
+	#[test]
+	fn glicko2_inactive_deviation_grows() {
+		let mut player = Glicko2 { deviation: 50.0, ..Glicko2::default() };
+		assert_eq!(player.preview().deviation, 50.0);
+		player.close_period();
+		assert!(player.deviation > 50.0 && player.rating == 1500.0);
+
+		let mut new_player = Glicko2::default();
+		new_player.close_period();
+		assert_eq!(new_player.deviation, MAX_DEVIATION);
+	}
+}
@@ -0,0 +1,276 @@
+use chess::{Board, ChessMove, Color};
+use chrono::{DateTime, Utc};
+use serenity::model::id::{GuildId, UserId};
+
+use discord_chess::san::ToSan;
+
+use crate::game::{ChannelGame, Termination};
+use crate::opening::{Opening, OpeningBook};
+
+/// A finished game, kept after its channel is freed for the next one
+pub struct GameRecord {
+	pub guild: GuildId,
+	pub rated: bool,
+	pub white: UserId,
+	pub black: UserId,
+	pub moves: Vec<ChessMove>,
+	pub termination: Termination,
+	pub finished: DateTime<Utc>,
+	pub opening: Option<Opening>,
+}
+
+impl GameRecord {
+	pub fn new(gm: &ChannelGame, termination: Termination, openings: &OpeningBook) -> GameRecord {
+		let moves = gm.moves();
+		GameRecord {
+			guild: gm.guild,
+			rated: gm.rated,
+			white: gm.white,
+			black: gm.black,
+			opening: openings.classify(&moves).cloned(),
+			moves,
+			termination,
+			finished: Utc::now(),
+		}
+	}
+
+	/// The moves of the game in SAN, in order
+	pub fn san_moves(&self) -> Vec<String> {
+		let mut board = Board::default();
+		self.moves.iter().map(|mv| {
+			let san = mv.to_san(&board);
+			board = board.make_move_new(*mv);
+			san
+		}).collect()
+	}
+
+	#[inline]
+	pub fn player(&self, color: Color) -> UserId {
+		match color {
+			Color::White => self.white,
+			Color::Black => self.black
+		}
+	}
+
+	#[inline]
+	pub fn is_between(&self, a: UserId, b: UserId) -> bool {
+		(self.white == a && self.black == b) || (self.white == b && self.black == a)
+	}
+
+	/// The position the game ended in
+	pub fn final_position(&self) -> Board {
+		self.moves.iter().fold(Board::default(), |board, mv| board.make_move_new(*mv))
+	}
+
+	/// The first `plies` moves of the game in SAN, numbered as in PGN
+	pub fn opening_line(&self, plies: usize) -> String {
+		let line: Vec<String> = self.san_moves().into_iter().take(plies).enumerate()
+			.map(|(i, san)| if i % 2 == 0 { format!("{}. {}", i / 2 + 1, san) } else { san })
+			.collect();
+		if line.is_empty() { "No moves".to_owned() } else { line.join(" ") }
+	}
+
+	/// The opening's name if it was recognised, otherwise its first `plies` moves
+	pub fn opening_label(&self, plies: usize) -> String {
+		match &self.opening {
+			Some(opening) => format!("{} {}", opening.eco, opening.name),
+			None => self.opening_line(plies),
+		}
+	}
+
+	/// Index of `user`'s result in a won, drawn, lost tally, or `None` if the game was aborted
+	pub fn result_for(&self, user: UserId) -> Option<usize> {
+		let white_score = self.termination.white_score()?;
+		let score = if self.white == user { white_score } else { 1.0 - white_score };
+		Some(if score > 0.5 { 0 } else if score < 0.5 { 2 } else { 1 })
+	}
+
+	pub fn to_pgn(&self, white_name: &str, black_name: &str) -> String {
+		let mut pgn = String::new();
+		pgn.push_str(if self.rated { "[Event \"Rated game\"]\n" } else { "[Event \"Casual game\"]\n" });
+		pgn.push_str("[Site \"Discord\"]\n");
+		pgn.push_str(&format!("[Date \"{}\"]\n", self.finished.format("%Y.%m.%d")));
+		pgn.push_str("[Round \"-\"]\n");
+		pgn.push_str(&format!("[White \"{}\"]\n", pgn_escape(white_name)));
+		pgn.push_str(&format!("[Black \"{}\"]\n", pgn_escape(black_name)));
+		pgn.push_str(&format!("[Result \"{}\"]\n", self.termination.pgn_result()));
+		pgn.push_str(&format!("[Termination \"{}\"]\n", self.termination.pgn_termination()));
+		if let Some(opening) = &self.opening {
+			pgn.push_str(&format!("[ECO \"{}\"]\n", opening.eco));
+			pgn.push_str(&format!("[Opening \"{}\"]\n", pgn_escape(&opening.name)));
+		}
+		pgn.push('\n');
+
+		let mut tokens = Vec::new();
+		for (i, san) in self.san_moves().into_iter().enumerate() {
+			if i % 2 == 0 {
+				tokens.push(format!("{}.", i / 2 + 1));
+			}
+			tokens.push(san);
+		}
+		tokens.push(self.termination.pgn_result().to_owned());
+
+		// Movetext lines are kept under 80 characters
+		let mut line = String::new();
+		for token in tokens {
+			if !line.is_empty() && line.len() + token.len() + 1 > 79 {
+				pgn.push_str(&line);
+				pgn.push('\n');
+				line.clear();
+			}
+			if !line.is_empty() {
+				line.push(' ');
+			}
+			line.push_str(&token);
+		}
+		pgn.push_str(&line);
+		pgn.push('\n');
+		pgn
+	}
+}
+
+fn pgn_escape(s: &str) -> String {
+	s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The record between two players, from the first player's point of view
+pub struct HeadToHead {
+	pub as_white: [u32; 3], // Wins, draws and losses
+	pub as_black: [u32; 3],
+	pub longest_streak: Option<(UserId, usize)>, // Most consecutive wins by either player
+}
+
+impl HeadToHead {
+	/// Tally the finished games between `player` and `opponent`, given in the order they were played
+	pub fn between<'a>(records: impl Iterator<Item = &'a GameRecord>, player: UserId, opponent: UserId) -> HeadToHead {
+		let mut h2h = HeadToHead { as_white: [0; 3], as_black: [0; 3], longest_streak: None };
+		let mut streak: Option<(UserId, usize)> = None;
+
+		for record in records.filter(|r| r.is_between(player, opponent)) {
+			let white_score = match record.termination.white_score() {
+				Some(score) => score,
+				None => continue, // Aborted
+			};
+			let (tally, score) = if record.white == player {
+				(&mut h2h.as_white, white_score)
+			} else {
+				(&mut h2h.as_black, 1.0 - white_score)
+			};
+			let winner = if score > 0.5 {
+				tally[0] += 1;
+				Some(player)
+			} else if score < 0.5 {
+				tally[2] += 1;
+				Some(opponent)
+			} else {
+				tally[1] += 1;
+				None
+			};
+
+			streak = match (winner, streak) {
+				(Some(w), Some((s, n))) if w == s => Some((w, n + 1)),
+				(Some(w), _) => Some((w, 1)),
+				(None, _) => None,
+			};
+			if let Some((w, n)) = streak {
+				if n > h2h.longest_streak.map_or(0, |(_, longest)| longest) {
+					h2h.longest_streak = Some((w, n));
+				}
+			}
+		}
+
+		h2h
+	}
+}
+
+/// `user`'s wins, draws and losses in each opening they have finished a game in, most played first
+pub fn opening_results<'a>(records: impl Iterator<Item = &'a GameRecord>, user: UserId) -> Vec<(String, [u32; 3])> {
+	let mut tallies: Vec<(String, [u32; 3])> = Vec::new();
+	for record in records.filter(|r| r.white == user || r.black == user) {
+		if let Some(result) = record.result_for(user) {
+			let label = record.opening_label(4);
+			match tallies.iter_mut().find(|(l, _)| *l == label) {
+				Some((_, tally)) => tally[result] += 1,
+				None => {
+					let mut tally = [0; 3];
+					tally[result] += 1;
+					tallies.push((label, tally));
+				}
+			}
+		}
+	}
+	tallies.sort_by_key(|(_, tally)| std::cmp::Reverse(tally.iter().sum::<u32>()));
+	tallies
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::game::{AutomaticDraw, DrawClaim};
+	use discord_chess::san::play_line;
+
+	fn record(moves: &str, termination: Termination) -> GameRecord {
+		let (_, moves) = play_line(&Board::default(), moves).unwrap_or_else(|e| panic!("{}", e));
+		GameRecord {
+			guild: GuildId(1),
+			rated: true,
+			white: UserId(2),
+			black: UserId(3),
+			moves,
+			termination,
+			finished: "2020-05-17T12:00:00Z".parse().unwrap(),
+			opening: None,
+		}
+	}
+
+	#[test]
+	fn pgn_export() {
+		let mut game = record("f3 e5 g4 Qh4#", Termination::Checkmate(Color::Black));
+		game.opening = Some(Opening { eco: "A02".to_owned(), name: "Bird's \"Opening\"".to_owned() });
+		assert_eq!(game.to_pgn("white#0001", "black\\#0002"), "\
+[Event \"Rated game\"]
+[Site \"Discord\"]
+[Date \"2020.05.17\"]
+[Round \"-\"]
+[White \"white#0001\"]
+[Black \"black\\\\#0002\"]
+[Result \"0-1\"]
+[Termination \"normal\"]
+[ECO \"A02\"]
+[Opening \"Bird's \\\"Opening\\\"\"]
+
+1. f3 e5 2. g4 Qh4# 0-1
+");
+	}
+
+	#[test]
+	fn pgn_terminations() {
+		for &(termination, result, tag) in &[
+			(Termination::Checkmate(Color::White), "1-0", "normal"),
+			(Termination::Resignation(Color::Black), "0-1", "normal"),
+			(Termination::Timeout(Color::White), "1-0", "time forfeit"),
+			(Termination::Abandonment(Color::Black), "0-1", "abandoned"),
+			(Termination::Stalemate, "1/2-1/2", "normal"),
+			(Termination::Agreement, "1/2-1/2", "normal"),
+			(Termination::Claimed(DrawClaim::ThreefoldRepetition), "1/2-1/2", "normal"),
+			(Termination::Automatic(AutomaticDraw::InsufficientMaterial), "1/2-1/2", "normal"),
+			(Termination::Adjudication(Some(Color::White)), "1-0", "adjudication"),
+			(Termination::Adjudication(None), "1/2-1/2", "adjudication"),
+			(Termination::Aborted, "*", "unterminated"),
+		] {
+			let pgn = record("e4", termination).to_pgn("a", "b");
+			assert!(pgn.contains(&format!("[Result \"{}\"]\n", result)), "{}", pgn);
+			assert!(pgn.contains(&format!("[Termination \"{}\"]\n", tag)), "{}", pgn);
+			assert!(pgn.ends_with(&format!("\n1. e4 {}\n", result)), "{}", pgn);
+		}
+	}
+
+	#[test]
+	fn long_movetext_wraps() {
+		let moves = "Nf3 Nf6 Ng1 Ng8 ".repeat(10);
+		let pgn = record(&moves, Termination::Agreement).to_pgn("a", "b");
+		let movetext = pgn.split("\n\n").nth(1).unwrap();
+		assert!(movetext.lines().count() > 1);
+		assert!(movetext.lines().all(|line| line.len() < 80));
+	}
+}
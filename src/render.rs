@@ -0,0 +1,335 @@
+use chess::{Board, ChessMove, Color, Piece, Square, ALL_COLORS, ALL_PIECES, ALL_SQUARES};
+use raster::{Color as Rgba, Image};
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::draw::{blit, draw_arrow, draw_text, fill_rect, resize, GifWriter, ImageFormat};
+use crate::pieces::PieceSet;
+use crate::san::ToSan;
+use crate::theme::{scaled, Theme, THEMES, BOARD_SIZE, BORDER_SIZE, SQUARE_SIZE};
+
+/// Rendered boards kept before the oldest are dropped
+const CACHE_CAPACITY: usize = 256;
+
+/// Square sizes boards can be drawn at. Piece tiles are `SQUARE_SIZE` pixels and scaled to the others
+pub const SQUARE_SIZES: [i32; 4] = [40, 60, 80, 120];
+
+// Panel measurements with `SQUARE_SIZE` squares, scaled with the squares
+pub const PANEL_WIDTH: i32 = 320;
+const PANEL_MARGIN: i32 = 20;
+const PANEL_TEXT: i32 = 24; // Glyph height; glyphs are half as wide
+const CAPTURED_SIZE: i32 = 28; // Captured pieces are drawn overlapping, at this size
+const MOVE_LINES: usize = 14;
+
+/// Colours for arrows and marked squares on diagrams
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Brush {
+	Green,
+	Red,
+	Blue,
+	Yellow,
+}
+
+impl Brush {
+	pub fn from_name(name: &str) -> Option<Brush> {
+		match name {
+			"green" => Some(Brush::Green),
+			"red" => Some(Brush::Red),
+			"blue" => Some(Brush::Blue),
+			"yellow" => Some(Brush::Yellow),
+			_ => None,
+		}
+	}
+
+	/// Translucent, so the board shows through
+	fn color(self) -> Rgba {
+		match self {
+			Brush::Green => Rgba::rgba(21, 120, 27, 170),
+			Brush::Red => Rgba::rgba(200, 32, 32, 170),
+			Brush::Blue => Rgba::rgba(0, 48, 136, 170),
+			Brush::Yellow => Rgba::rgba(230, 200, 0, 170),
+		}
+	}
+}
+
+/// An arrow written as a move, `e2e4`, optionally followed by a colour, `e2e4red`
+pub fn parse_arrow(text: &str) -> Option<(Square, Square, Brush)> {
+	if text.len() < 4 || !text.is_char_boundary(4) {
+		return None;
+	}
+	let from = Square::from_str(&text[..2]).ok()?;
+	let to = Square::from_str(&text[2..4]).ok()?;
+	let brush = if text.len() == 4 { Brush::Green } else { Brush::from_name(&text[4..])? };
+	if from == to { None } else { Some((from, to, brush)) }
+}
+
+/// A marked square written as `@d5`, optionally followed by a colour, `@d5red`
+pub fn parse_mark(text: &str) -> Option<(Square, Brush)> {
+	let text = text.strip_prefix('@')?;
+	if text.len() < 2 || !text.is_char_boundary(2) {
+		return None;
+	}
+	let square = Square::from_str(&text[..2]).ok()?;
+	let brush = if text.len() == 2 { Brush::Green } else { Brush::from_name(&text[2..])? };
+	Some((square, brush))
+}
+
+/// Shown to the right of the board: the players, the pieces each has taken, who is ahead in material and the last few moves
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Panel {
+	pub names: [String; 2], // Indexed by `Color::to_index`
+	pub moves: Vec<ChessMove>, // Every move from the starting position
+}
+
+/// Everything besides the position that decides how a board image looks
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RenderOptions {
+	pub theme: String, // Unknown themes and piece sets fall back to the defaults
+	pub pieces: String,
+	pub orientation: Color, // The side shown at the bottom
+	pub last_move: Option<ChessMove>, // Highlighted
+	pub highlights: Vec<Square>, // Also highlighted
+	pub marks: Vec<(Square, Brush)>, // Squares filled in a colour
+	pub arrows: Vec<(Square, Square, Brush)>, // Drawn over the pieces
+	pub panel: Option<Panel>,
+	pub square_size: i32, // One of `SQUARE_SIZES`, or the board is drawn with `SQUARE_SIZE` squares
+	pub format: ImageFormat, // For `render_file`
+}
+
+impl Default for RenderOptions {
+	fn default() -> RenderOptions {
+		RenderOptions { theme: THEMES[0].name.to_owned(), pieces: String::new(), orientation: Color::White, last_move: None, highlights: Vec::new(), marks: Vec::new(), arrows: Vec::new(), panel: None,
+			square_size: SQUARE_SIZE, format: ImageFormat::Png }
+	}
+}
+
+type RenderKey = (Board, RenderOptions);
+
+/// A theme's name, whether the board is seen from black and its square size
+type BackgroundKey = (&'static str, bool, i32);
+
+/// A piece set's tiles at another size, indexed by `Color::to_index` * 6 + `Piece::to_index`
+type ScaledTiles = Arc<Vec<Image>>;
+
+/// Encoded boards, forgetting the oldest once `CACHE_CAPACITY` are kept
+#[derive(Default)]
+struct RenderCache {
+	files: HashMap<RenderKey, Arc<Vec<u8>>>,
+	order: VecDeque<RenderKey>, // Oldest first
+}
+
+/// Draws boards from pre-sliced piece tiles, keeping each theme's empty board and recently drawn positions
+pub struct Renderer {
+	font: Image,
+	pub piece_sets: Vec<PieceSet>, // The first is the default
+	backgrounds: Mutex<HashMap<BackgroundKey, Arc<Image>>>,
+	scaled: Mutex<HashMap<(String, i32), ScaledTiles>>, // By set name and size
+	cache: Mutex<RenderCache>,
+}
+
+impl Renderer {
+	pub fn new(font: Image, piece_sets: Vec<PieceSet>) -> Renderer {
+		Renderer { font, piece_sets, backgrounds: Mutex::new(HashMap::new()), scaled: Mutex::new(HashMap::new()), cache: Mutex::new(RenderCache::default()) }
+	}
+
+	/// The piece set called `name`, or the default
+	pub fn piece_set(&self, name: &str) -> &PieceSet {
+		self.piece_sets.iter().find(|set| set.name.eq_ignore_ascii_case(name)).unwrap_or(&self.piece_sets[0])
+	}
+
+	fn background(&self, theme: &'static Theme, use_black: bool, square: i32) -> Arc<Image> {
+		self.backgrounds.lock().unwrap()
+			.entry((theme.name, use_black, square))
+			.or_insert_with(|| Arc::new(theme.background(&self.font, use_black, square)))
+			.clone()
+	}
+
+	/// `set`'s tiles resized to `size` pixels square, resized only the first time they're asked for
+	fn scaled_tiles(&self, set: &PieceSet, size: i32) -> ScaledTiles {
+		self.scaled.lock().unwrap()
+			.entry((set.name.clone(), size))
+			.or_insert_with(|| Arc::new(
+				ALL_COLORS.iter()
+					.flat_map(|&color| ALL_PIECES.iter().map(move |&piece| resize(set.tile(piece, color), size, size)))
+					.collect()
+			))
+			.clone()
+	}
+
+	/// Draw `position` with the squares of the last move and any others asked for highlighted, a king in check marked,
+	/// and any marks and arrows on top
+	pub fn render(&self, position: &Board, options: &RenderOptions) -> Image {
+		let theme = Theme::by_name(&options.theme).unwrap_or(&THEMES[0]);
+		let pieces = self.piece_set(&options.pieces);
+		let use_black = options.orientation == Color::Black;
+		let size = if SQUARE_SIZES.contains(&options.square_size) { options.square_size } else { SQUARE_SIZE };
+		let tiles = if size == SQUARE_SIZE { None } else { Some(self.scaled_tiles(pieces, size)) };
+		let mut board = (*self.background(theme, use_black, size)).clone();
+		let in_check = if position.checkers().popcnt() > 0 { Some(position.king_square(position.side_to_move())) } else { None };
+
+		for &square in ALL_SQUARES.iter() {
+			let (x, y) = square_corner(square, use_black, size);
+
+			if options.last_move.is_some_and(|mv| square == mv.get_source() || square == mv.get_dest()) || options.highlights.contains(&square) {
+				fill_rect(&mut board, x, y, size, size, &theme.highlight);
+			}
+			if in_check == Some(square) {
+				fill_rect(&mut board, x, y, size, size, &theme.check);
+			}
+			for &(_, brush) in options.marks.iter().filter(|(marked, _)| *marked == square) {
+				fill_rect(&mut board, x, y, size, size, &brush.color());
+			}
+			if let (Some(piece), Some(color)) = (position.piece_on(square), position.color_on(square)) {
+				let tile = match &tiles {
+					Some(tiles) => &tiles[color.to_index() * 6 + piece.to_index()],
+					None => pieces.tile(piece, color),
+				};
+				blit(&mut board, tile, x, y);
+			}
+		}
+
+		for &(from, to, brush) in &options.arrows {
+			let centre = |square| {
+				let (x, y) = square_corner(square, use_black, size);
+				((x + size / 2) as f32, (y + size / 2) as f32)
+			};
+			let width = size as f32;
+			draw_arrow(&mut board, centre(from), centre(to), width * 0.2, width * 0.4, &brush.color());
+		}
+
+		match &options.panel {
+			Some(panel) => self.with_panel(&board, position, panel, theme, pieces, options.orientation, size),
+			None => board,
+		}
+	}
+
+	/// `board`, with `square` pixel squares, with `panel` drawn beside it, the player at the bottom of the board listed at the bottom
+	#[allow(clippy::too_many_arguments)]
+	fn with_panel(&self, board: &Image, position: &Board, panel: &Panel, theme: &Theme, pieces: &PieceSet, bottom: Color, square: i32) -> Image {
+		let (board_size, border) = (scaled(BOARD_SIZE, square), scaled(BORDER_SIZE, square));
+		let (width, text, captured_size) = (scaled(PANEL_WIDTH, square), scaled(PANEL_TEXT, square), scaled(CAPTURED_SIZE, square));
+		let (gap, lead_gap, line_gap) = (scaled(12, square), scaled(4, square), scaled(8, square));
+		let max_chars = ((width - scaled(PANEL_MARGIN, square) * 2) / (text / 2)) as usize;
+		let mut img = Image::blank(board_size + width, board_size);
+		fill_rect(&mut img, 0, 0, board_size + width, board_size, &theme.border);
+		blit(&mut img, board, 0, 0);
+		let left = board_size + scaled(PANEL_MARGIN, square);
+
+		// Replay the game for its SAN and captures
+		let mut replay = Board::default();
+		let mut san = Vec::with_capacity(panel.moves.len());
+		let mut captured: [Vec<Piece>; 2] = [Vec::new(), Vec::new()]; // Taken by each colour
+		for &mv in &panel.moves {
+			let taken = match replay.piece_on(mv.get_dest()) {
+				None if replay.piece_on(mv.get_source()) == Some(Piece::Pawn) && mv.get_source().get_file() != mv.get_dest().get_file() => Some(Piece::Pawn),
+				taken => taken,
+			};
+			if let Some(taken) = taken {
+				captured[replay.side_to_move().to_index()].push(taken);
+			}
+			san.push(mv.to_san(&replay));
+			replay = replay.make_move_new(mv);
+		}
+
+		let material = |color: Color| -> i32 {
+			[(Piece::Pawn, 1), (Piece::Knight, 3), (Piece::Bishop, 3), (Piece::Rook, 5), (Piece::Queen, 9)].iter()
+				.map(|&(piece, value)| value * (position.pieces(piece) & position.color_combined(color)).popcnt() as i32)
+				.sum()
+		};
+		let small = self.scaled_tiles(pieces, captured_size);
+
+		for &(color, name_y, captured_y) in &[
+			(!bottom, border, border + text + gap),
+			(bottom, board_size - border - text, board_size - border - text - gap - captured_size),
+		] {
+			let name: String = panel.names[color.to_index()].chars().take(max_chars).collect();
+			draw_text(&mut img, &self.font, &name, left, name_y, text, &theme.label);
+
+			let mut taken = captured[color.to_index()].clone();
+			taken.sort_by_key(|piece| piece.to_index());
+			let mut x = left;
+			for (i, &piece) in taken.iter().enumerate() {
+				if i > 0 {
+					x += if taken[i - 1] == piece { captured_size / 2 } else { captured_size };
+				}
+				blit(&mut img, &small[(!color).to_index() * 6 + piece.to_index()], x, captured_y);
+			}
+			let lead = material(color) - material(!color);
+			if lead > 0 {
+				let x = if taken.is_empty() { left } else { x + captured_size + lead_gap };
+				draw_text(&mut img, &self.font, &format!("+{}", lead), x, captured_y + (captured_size - text) / 2, text, &theme.label);
+			}
+		}
+
+		// One line per move number, the latest at the bottom
+		let lines: Vec<String> = san.chunks(2).enumerate()
+			.map(|(i, pair)| format!("{:>3}. {:<8}{}", i + 1, pair[0], pair.get(1).map(String::as_str).unwrap_or("")))
+			.collect();
+		let line_height = text + line_gap;
+		let top = (board_size - line_height * MOVE_LINES as i32) / 2;
+		for (row, line) in lines.iter().skip(lines.len().saturating_sub(MOVE_LINES)).enumerate() {
+			let line: String = line.chars().take(max_chars).collect();
+			draw_text(&mut img, &self.font, &line, left, top + row as i32 * line_height, text, &theme.label);
+		}
+
+		img
+	}
+
+	/// An animated GIF stepping through `moves` from the starting position with each move highlighted,
+	/// showing every position for `delay` hundredths of a second and the final one for four times as long
+	pub fn render_replay(&self, moves: &[ChessMove], options: &RenderOptions, delay: u16) -> Vec<u8> {
+		let frame = |ply: usize, position: &Board| {
+			let mut options = options.clone();
+			options.last_move = ply.checked_sub(1).map(|last| moves[last]);
+			if let Some(panel) = &mut options.panel {
+				panel.moves = moves[..ply].to_vec();
+			}
+			self.render(position, &options)
+		};
+
+		let positions: Vec<Board> = std::iter::once(Board::default())
+			.chain(moves.iter().scan(Board::default(), |board, &mv| {
+				*board = board.make_move_new(mv);
+				Some(*board)
+			}))
+			.collect();
+		let (first, last) = (frame(0, &positions[0]), frame(moves.len(), &positions[moves.len()]));
+		let mut gif = GifWriter::new(first.width, first.height, &[&first, &last]);
+		gif.add_frame(first, delay);
+		for (ply, position) in positions.iter().enumerate().skip(1).take(moves.len().saturating_sub(1)) {
+			gif.add_frame(frame(ply, position), delay);
+		}
+		if !moves.is_empty() {
+			gif.add_frame(last, delay.saturating_mul(4));
+		}
+		gif.finish()
+	}
+
+	/// `render` encoded in `options.format`, reusing the encoding if the same board was drawn recently
+	pub fn render_file(&self, position: &Board, options: &RenderOptions) -> Arc<Vec<u8>> {
+		let key = (*position, options.clone());
+		if let Some(file) = self.cache.lock().unwrap().files.get(&key) {
+			return file.clone();
+		}
+
+		let file = Arc::new(options.format.encode(&self.render(position, options)));
+		let mut cache = self.cache.lock().unwrap();
+		if !cache.files.contains_key(&key) { // Another thread may have drawn it meanwhile
+			if cache.order.len() >= CACHE_CAPACITY {
+				let oldest = cache.order.pop_front().unwrap();
+				cache.files.remove(&oldest);
+			}
+			cache.order.push_back(key.clone());
+			cache.files.insert(key, file.clone());
+		}
+		file
+	}
+}
+
+/// Top left corner of `square` in a board image with `size` pixel squares
+fn square_corner(square: Square, use_black: bool, size: i32) -> (i32, i32) {
+	let (file, rank) = (square.get_file().to_index() as i32, square.get_rank().to_index() as i32);
+	let (col, row) = if use_black { (7 - file, rank) } else { (file, 7 - rank) };
+	(scaled(BORDER_SIZE, size) + col * size, scaled(BORDER_SIZE, size) + row * size)
+}
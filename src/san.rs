@@ -0,0 +1,511 @@
+use chess::*;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+pub enum MoveError {
+	Illegal,
+	IllFormed,
+	Ambiguous,
+}
+
+/// A move in a line which couldn't be played, and why
+pub struct SanError<'a> {
+	pub san: &'a str,
+	pub error: MoveError,
+}
+
+impl fmt::Display for SanError<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.error {
+			MoveError::Illegal => write!(f, "Illegal move: {}", self.san),
+			MoveError::IllFormed => write!(f, "Ill-formed move: {}", self.san),
+			MoveError::Ambiguous => write!(f, "Ambiguous move: {}", self.san),
+		}
+	}
+}
+
+pub trait FromSan {
+	fn from_san(board: &Board, move_text: &str) -> Result<ChessMove, MoveError>;
+}
+
+impl FromSan for ChessMove {
+	//* Ripped from https://github.com/jordanbray/chess/blob/master/src/chess_move.rs
+	//* Not my algorithm
+
+	/// Convert a SAN (Standard Algebraic Notation) move into a `ChessMove`
+	///
+	/// ```
+	/// use chess::{Board, ChessMove, Square};
+	///
+	/// let board = Board::default();
+	/// assert_eq!(
+	///     ChessMove::from_san(&board, "e4").expect("e4 is valid in the initial position"),
+	///     ChessMove::new(Square::E2, Square::E4, None)
+	/// );
+	/// ```
+	fn from_san(board: &Board, move_text: &str) -> Result<ChessMove, MoveError> {
+		// Castles first...
+		if move_text == "O-O" || move_text == "O-O-O" {
+			let rank = board.side_to_move().to_my_backrank();
+			let source_file = File::E;
+			let dest_file = if move_text == "O-O" { File::G } else { File::C };
+
+			let m = ChessMove::new(
+				Square::make_square(rank, source_file),
+				Square::make_square(rank, dest_file),
+				None,
+			);
+			if MoveGen::new_legal(board).any(|l| l == m) {
+				return Ok(m);
+			} else {
+				return Err(MoveError::Illegal);
+			}
+		}
+
+		// forms of SAN moves
+		// a4 (Pawn moves to a4)
+		// exd4 (Pawn on e file takes on d4)
+		// xd4 (Illegal, source file must be specified)
+		// 1xd4 (Illegal, source file (not rank) must be specified)
+		// Nc3 (Knight (or any piece) on *some square* to c3
+		// Nb1c3 (Knight (or any piece) on b1 to c3
+		// Nbc3 (Knight on b file to c3)
+		// N1c3 (Knight on first rank to c3)
+		// Nb1xc3 (Knight on b1 takes on c3)
+		// Nbxc3 (Knight on b file takes on c3)
+		// N1xc3 (Knight on first rank takes on c3)
+		// Nc3+ (Knight moves to c3 with check)
+		// Nc3# (Knight moves to c3 with checkmate)
+
+		// Because I'm dumb, I'm wondering if a hash table of all possible moves would be stupid.
+		// There are only 186624 possible moves in SAN notation.
+		//
+		// Would this even be faster?  Somehow I doubt it because caching, but maybe, I dunno...
+		// This could take the form of a:
+		// struct CheckOrCheckmate {
+		//      Neither,
+		//      Check,
+		//      CheckMate,
+		// }
+		// struct FromSan {
+		//      piece: Piece,
+		//      source: Vec<Square>, // possible source squares
+		//      // OR
+		//      source_rank: Option<Rank>,
+		//      source_file: Option<File>,
+		//      dest: Square,
+		//      takes: bool,
+		//      check: CheckOrCheckmate
+		// }
+		//
+		// This could be kept internally as well, and never tell the user about such an abomination
+		//
+		// I estimate this table would take around 2 MiB, but I had to approximate some things.  It
+		// may be less
+
+		// This can be described with the following format
+		// [Optional Piece Specifier] ("" | "N" | "B" | "R" | "Q" | "K")
+		// [Optional Source Specifier] ( "" | "a-h" | "1-8" | ("a-h" + "1-8"))
+		// [Optional Takes Specifier] ("" | "x")
+		// [Full Destination Square] ("a-h" + "0-8")
+		// [Optional Promotion Specifier] ("" | "=N" | "=B" | "=R" | "=Q")
+		// [Optional Check(mate) Specifier] ("" | "+" | "#")
+		// [Optional En Passant Specifier] ("" | " e.p.")
+
+		let mut cur_index: usize = 0;
+		let moving_piece = match move_text
+			.get(cur_index..(cur_index + 1))
+			.ok_or(MoveError::IllFormed)?
+		{
+			"N" => {
+				cur_index += 1;
+				Piece::Knight
+			}
+			"B" => {
+				cur_index += 1;
+				Piece::Bishop
+			}
+			"Q" => {
+				cur_index += 1;
+				Piece::Queen
+			}
+			"R" => {
+				cur_index += 1;
+				Piece::Rook
+			}
+			"K" => {
+				cur_index += 1;
+				Piece::King
+			}
+			_ => Piece::Pawn,
+		};
+
+		let mut source_file = match move_text
+			.get(cur_index..(cur_index + 1))
+			.ok_or(MoveError::IllFormed)?
+		{
+			"a" => {
+				cur_index += 1;
+				Some(File::A)
+			}
+			"b" => {
+				cur_index += 1;
+				Some(File::B)
+			}
+			"c" => {
+				cur_index += 1;
+				Some(File::C)
+			}
+			"d" => {
+				cur_index += 1;
+				Some(File::D)
+			}
+			"e" => {
+				cur_index += 1;
+				Some(File::E)
+			}
+			"f" => {
+				cur_index += 1;
+				Some(File::F)
+			}
+			"g" => {
+				cur_index += 1;
+				Some(File::G)
+			}
+			"h" => {
+				cur_index += 1;
+				Some(File::H)
+			}
+			_ => None,
+		};
+
+		let mut source_rank = match move_text
+			.get(cur_index..(cur_index + 1))
+			.ok_or(MoveError::IllFormed)?
+		{
+			"1" => {
+				cur_index += 1;
+				Some(Rank::First)
+			}
+			"2" => {
+				cur_index += 1;
+				Some(Rank::Second)
+			}
+			"3" => {
+				cur_index += 1;
+				Some(Rank::Third)
+			}
+			"4" => {
+				cur_index += 1;
+				Some(Rank::Fourth)
+			}
+			"5" => {
+				cur_index += 1;
+				Some(Rank::Fifth)
+			}
+			"6" => {
+				cur_index += 1;
+				Some(Rank::Sixth)
+			}
+			"7" => {
+				cur_index += 1;
+				Some(Rank::Seventh)
+			}
+			"8" => {
+				cur_index += 1;
+				Some(Rank::Eighth)
+			}
+			_ => None,
+		};
+
+		let takes = if let Some("x") = move_text.get(cur_index..(cur_index + 1)) {
+			cur_index += 1;
+			true
+		} else {
+			false
+		};
+
+		let dest = if let Some(s) = move_text.get(cur_index..(cur_index + 2)) {
+			if let Ok(q) = Square::from_str(s) {
+				cur_index += 2;
+				q
+			} else {
+				let sq = Square::make_square(
+					source_rank.ok_or(MoveError::IllFormed)?,
+					source_file.ok_or(MoveError::IllFormed)?,
+				);
+				source_rank = None;
+				source_file = None;
+				sq
+			}
+		} else {
+			let sq = Square::make_square(
+				source_rank.ok_or(MoveError::IllFormed)?,
+				source_file.ok_or(MoveError::IllFormed)?,
+			);
+			source_rank = None;
+			source_file = None;
+			sq
+		};
+
+		let promotion = if let Some(n) = move_text.get(cur_index..(cur_index + 1)) {
+			if n == "=" {
+				if let Some(s) = move_text.get((cur_index + 1)..(cur_index + 2)) {
+					match s {
+						"N" => {
+							cur_index += 2;
+							Some(Piece::Knight)
+						}
+						"B" => {
+							cur_index += 2;
+							Some(Piece::Bishop)
+						}
+						"R" => {
+							cur_index += 2;
+							Some(Piece::Rook)
+						}
+						"Q" => {
+							cur_index += 2;
+							Some(Piece::Queen)
+						}
+						_ => None,
+					}
+				} else {
+					None
+				}
+			} else {
+				None
+			}
+		} else {
+			None
+		};
+
+		if let Some(s) = move_text.get(cur_index..(cur_index + 1)) {
+			let _maybe_check_or_mate = match s {
+				"+" => {
+					cur_index += 1;
+					Some(false)
+				}
+				"#" => {
+					cur_index += 1;
+					Some(true)
+				}
+				_ => None,
+			};
+		}
+
+		let ep = if let Some(s) = move_text.get(cur_index..) {
+			s == " e.p."
+		} else {
+			false
+		};
+
+		//if ep {
+		//    cur_index += 5;
+		//}
+
+		// Ok, now we have all the data from the SAN move, in the following structures
+		// moving_piece, source_rank, source_file, taks, dest, promotion, maybe_check_or_mate, and
+		// ep
+
+		let mut found_move: Option<ChessMove> = None;
+		for m in &mut MoveGen::new_legal(board) {
+			// check that the move has the properties specified
+			if board.piece_on(m.get_source()) != Some(moving_piece) {
+				continue;
+			}
+
+			if let Some(rank) = source_rank {
+				if m.get_source().get_rank() != rank {
+					continue;
+				}
+			}
+
+			if let Some(file) = source_file {
+				if m.get_source().get_file() != file {
+					continue;
+				}
+			}
+
+			if m.get_dest() != dest {
+				continue;
+			}
+
+			if m.get_promotion() != promotion {
+				continue;
+			}
+
+			if found_move.is_some() {
+				// There's more than one move which fits the properties
+				return Err(MoveError::Ambiguous);
+			}
+
+			// takes is complicated, because of e.p.
+			// A pawn changing file onto an empty square is capturing en passant, whether or not " e.p." was written
+			let en_passant = moving_piece == Piece::Pawn && m.get_source().get_file() != m.get_dest().get_file() && board.piece_on(m.get_dest()).is_none();
+			if takes != (board.piece_on(m.get_dest()).is_some() || en_passant) {
+				continue;
+			}
+
+			if ep && !en_passant {
+				continue;
+			}
+
+			found_move = Some(m);
+		}
+
+		found_move.ok_or(MoveError::Illegal)
+	}
+}
+
+pub trait ToSan {
+	fn to_san(&self, board: &Board) -> String;
+}
+
+impl ToSan for ChessMove {
+	/// Convert a `ChessMove` into SAN, as it would be played from `board`
+	fn to_san(&self, board: &Board) -> String {
+		let source = self.get_source();
+		let dest = self.get_dest();
+		let piece = board.piece_on(source).unwrap_or(Piece::Pawn);
+		let mut san = String::new();
+
+		if piece == Piece::King && source.get_file() == File::E && (dest.get_file() == File::G || dest.get_file() == File::C) {
+			san.push_str(if dest.get_file() == File::G { "O-O" } else { "O-O-O" });
+		} else {
+			let capture = board.piece_on(dest).is_some() || (piece == Piece::Pawn && source.get_file() != dest.get_file());
+			if piece == Piece::Pawn {
+				if capture {
+					san.push_str(&source.to_string()[0..1]);
+				}
+			} else {
+				san.push_str(&piece.to_string(Color::White));
+				let others: Vec<Square> = MoveGen::new_legal(board)
+					.filter(|m| m.get_dest() == dest && m.get_source() != source && board.piece_on(m.get_source()) == Some(piece))
+					.map(|m| m.get_source())
+					.collect();
+				if !others.is_empty() {
+					let name = source.to_string();
+					if others.iter().all(|sq| sq.get_file() != source.get_file()) {
+						san.push_str(&name[0..1]);
+					} else if others.iter().all(|sq| sq.get_rank() != source.get_rank()) {
+						san.push_str(&name[1..2]);
+					} else {
+						san.push_str(&name);
+					}
+				}
+			}
+			if capture {
+				san.push('x');
+			}
+			san.push_str(&dest.to_string());
+			if let Some(promotion) = self.get_promotion() {
+				san.push('=');
+				san.push_str(&promotion.to_string(Color::White));
+			}
+		}
+
+		let after = board.make_move_new(*self);
+		if after.status() == BoardStatus::Checkmate {
+			san.push('#');
+		} else if after.checkers().popcnt() > 0 {
+			san.push('+');
+		}
+		san
+	}
+}
+
+/// Play `line`, moves in SAN separated by whitespace, from `start`. Move numbers like `1.` are skipped.
+/// Returns the final position and the moves played
+pub fn play_line<'a>(start: &Board, line: &'a str) -> Result<(Board, Vec<ChessMove>), SanError<'a>> {
+	let mut board = *start;
+	let mut moves = Vec::new();
+	for san in line.split_whitespace().filter(|token| !token.ends_with('.')) {
+		let mv = <ChessMove as FromSan>::from_san(&board, san).map_err(|error| SanError { san, error })?;
+		board = board.make_move_new(mv);
+		moves.push(mv);
+	}
+	Ok((board, moves))
+}
+
+/// Read a position from FEN. Positions without exactly one king a side are refused, since the chess crate
+/// aborts on them instead of returning an error
+pub fn parse_fen(fen: &str) -> Option<Board> {
+	let builder = BoardBuilder::from_str(fen).ok()?;
+	for &color in &ALL_COLORS {
+		if ALL_SQUARES.iter().filter(|&&square| builder[square] == Some((Piece::King, color))).count() != 1 {
+			return None;
+		}
+	}
+	Board::try_from(builder).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The SAN of the move from `from` to `to` in the position `fen`, checking it reads back as the same move
+	fn san(fen: &str, from: Square, to: Square, promotion: Option<Piece>) -> String {
+		let board = Board::from_str(fen).unwrap();
+		let mv = ChessMove::new(from, to, promotion);
+		let san = mv.to_san(&board);
+		assert!(<ChessMove as FromSan>::from_san(&board, &san).ok() == Some(mv), "{} doesn't read back", san);
+		san
+	}
+
+	#[test]
+	fn check_and_mate_suffixes() {
+		assert_eq!(san("rnbqkbnr/ppppp1pp/8/5p2/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2", Square::D1, Square::H5, None), "Qh5+");
+		assert_eq!(san("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", Square::D8, Square::H4, None), "Qh4#");
+	}
+
+	#[test]
+	fn disambiguation() {
+		assert_eq!(san("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", Square::B1, Square::D2, None), "Nbd2");
+		assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", Square::A1, Square::A3, None), "R1a3");
+		assert_eq!(san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", Square::A1, Square::C3, None), "Qa1c3");
+		assert_eq!(san("4k3/8/8/8/8/5N2/8/4K3 w - - 0 1", Square::F3, Square::D2, None), "Nd2");
+	}
+
+	#[test]
+	fn en_passant() {
+		let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+		assert_eq!(san(fen, Square::E5, Square::D6, None), "exd6");
+		let board = Board::from_str(fen).unwrap();
+		let capture = Some(ChessMove::new(Square::E5, Square::D6, None));
+		assert!(<ChessMove as FromSan>::from_san(&board, "exd6 e.p.").ok() == capture);
+		assert!(<ChessMove as FromSan>::from_san(&board, "d6").is_err());
+		assert!(<ChessMove as FromSan>::from_san(&board, "e6 e.p.").is_err());
+	}
+
+	#[test]
+	fn pawn_captures_and_promotion() {
+		assert_eq!(san("8/P3k3/8/8/8/8/8/4K3 w - - 0 1", Square::A7, Square::A8, Some(Piece::Queen)), "a8=Q");
+		assert_eq!(san("1r6/P3k3/8/8/8/8/8/4K3 w - - 0 1", Square::A7, Square::B8, Some(Piece::Knight)), "axb8=N");
+	}
+
+	#[test]
+	fn lines() {
+		let (board, moves) = play_line(&Board::default(), "1. e4 e5 2. Nf3").ok().unwrap();
+		assert_eq!(moves.len(), 3);
+		assert!(board == Board::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2").unwrap());
+		assert_eq!(play_line(&Board::default(), "e4 e5 Ke3").err().unwrap().to_string(), "Illegal move: Ke3");
+	}
+
+	#[test]
+	fn fen_needs_one_king_each() {
+		assert!(parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1") == Some(Board::default()));
+		assert!(parse_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_none());
+		assert!(parse_fen("4k3/8/8/8/8/8/8/8 w - - 0 1").is_none());
+		assert!(parse_fen("4k3/8/8/8/8/8/8/2K1K3 w - - 0 1").is_none());
+		assert!(parse_fen("not a position").is_none());
+	}
+
+	#[test]
+	fn castling() {
+		let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+		assert_eq!(san(fen, Square::E1, Square::G1, None), "O-O");
+		assert_eq!(san(fen, Square::E1, Square::C1, None), "O-O-O");
+	}
+}
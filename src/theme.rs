@@ -0,0 +1,100 @@
+use raster::{Color, Image};
+
+use crate::draw::{fill_rect, draw_text};
+
+pub const SQUARE_SIZE: i32 = 80;
+pub const BORDER_SIZE: i32 = 40;
+pub const BOARD_SIZE: i32 = SQUARE_SIZE * 8 + BORDER_SIZE * 2;
+
+/// `length`, given for a board with `SQUARE_SIZE` squares, for one with `square` pixel squares
+pub const fn scaled(length: i32, square: i32) -> i32 {
+	length * square / SQUARE_SIZE
+}
+
+/// Colours a board is drawn in
+pub struct Theme {
+	pub name: &'static str,
+	pub border: Color,
+	pub label: Color, // Rank and file names in the border
+	pub light: Color,
+	pub dark: Color,
+	pub highlight: Color, // Drawn over the squares of the last move
+	pub check: Color, // Drawn under a king in check
+}
+
+const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+	Color { r, g, b, a }
+}
+
+/// The first theme is the default
+pub const THEMES: [Theme; 4] = [
+	Theme {
+		name: "green",
+		border: rgba(42, 42, 42, 255),
+		label: rgba(255, 254, 241, 255),
+		light: rgba(236, 252, 235, 255),
+		dark: rgba(91, 181, 81, 255),
+		highlight: rgba(255, 255, 127, 255),
+		check: rgba(255, 83, 83, 255),
+	},
+	Theme {
+		name: "brown",
+		border: rgba(48, 38, 30, 255),
+		label: rgba(240, 217, 181, 255),
+		light: rgba(240, 217, 181, 255),
+		dark: rgba(181, 136, 99, 255),
+		highlight: rgba(155, 199, 0, 105),
+		check: rgba(230, 40, 20, 170),
+	},
+	Theme {
+		name: "blue",
+		border: rgba(36, 44, 52, 255),
+		label: rgba(222, 227, 230, 255),
+		light: rgba(222, 227, 230, 255),
+		dark: rgba(140, 162, 173, 255),
+		highlight: rgba(155, 199, 0, 105),
+		check: rgba(230, 40, 20, 170),
+	},
+	Theme {
+		name: "gray",
+		border: rgba(30, 30, 30, 255),
+		label: rgba(230, 230, 230, 255),
+		light: rgba(210, 210, 210, 255),
+		dark: rgba(140, 140, 140, 255),
+		highlight: rgba(255, 255, 127, 150),
+		check: rgba(255, 83, 83, 190),
+	},
+];
+
+impl Theme {
+	pub fn by_name(name: &str) -> Option<&'static Theme> {
+		THEMES.iter().find(|theme| theme.name.eq_ignore_ascii_case(name))
+	}
+
+	/// Draw the empty board with `square` pixel squares and rank and file names, seen from Black's side if `use_black`.
+	/// The border and labels keep their proportions to the squares at `SQUARE_SIZE`
+	pub fn background(&self, font: &Image, use_black: bool, square: i32) -> Image {
+		let border = scaled(BORDER_SIZE, square);
+		let board = scaled(BOARD_SIZE, square);
+		let label_height = scaled(28, square);
+		let label_width = label_height / 2;
+
+		let mut img = Image::blank(board, board);
+		fill_rect(&mut img, 0, 0, board, board, &self.border);
+		for y in 0..8 {
+			for x in 0..8 {
+				let color = if (x + y) % 2 == 0 { &self.light } else { &self.dark };
+				fill_rect(&mut img, border + x * square, border + y * square, square, square, color);
+			}
+		}
+		for i in 0..8 {
+			let (rank, file) = if use_black { (i + 1, 7 - i) } else { (8 - i, i) };
+			let centre = border + i * square + square / 2;
+			let rank = rank.to_string();
+			let file = ((b'a' + file as u8) as char).to_string();
+			draw_text(&mut img, font, &rank, (border - label_width) / 2, centre - label_height / 2, label_height, &self.label);
+			draw_text(&mut img, font, &file, centre - label_width / 2, board - (border + label_height) / 2, label_height, &self.label);
+		}
+		img
+	}
+}
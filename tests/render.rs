@@ -0,0 +1,192 @@
+//! Compares rendered boards with the images in `tests/golden`.
+//! After an intended change to rendering, run `UPDATE_GOLDEN=1 cargo test --test render` and check the new images by eye
+use chess::{Board, ChessMove, Color, Square};
+use discord_chess::draw::ImageFormat;
+use discord_chess::pieces::PieceSet;
+use discord_chess::render::{parse_arrow, parse_mark, Brush, Panel, RenderOptions, Renderer};
+use discord_chess::san::play_line;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn renderer() -> Renderer {
+	Renderer::new(raster::open("res/font.png").unwrap(), PieceSet::load_all("res/pieces"))
+}
+
+/// Decode a PNG into its size and RGBA pixels
+fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+	let (info, mut reader) = png::Decoder::new(png).read_info().unwrap();
+	assert_eq!(info.color_type, png::ColorType::RGBA);
+	let mut pixels = vec![0; info.buffer_size()];
+	reader.next_frame(&mut pixels).unwrap();
+	(info.width, info.height, pixels)
+}
+
+fn check(name: &str, position: &str, options: RenderOptions) {
+	let png = renderer().render_file(&Board::from_str(position).unwrap(), &options);
+	let path: PathBuf = ["tests", "golden", &format!("{}.png", name)].iter().collect();
+	if std::env::var_os("UPDATE_GOLDEN").is_some() {
+		std::fs::write(&path, &*png).unwrap();
+		return;
+	}
+
+	let expected = std::fs::read(&path).unwrap_or_else(|_| panic!("{} is missing; run with UPDATE_GOLDEN=1 to create it", path.display()));
+	let (width, height, actual) = decode(&png);
+	let (expected_width, expected_height, expected) = decode(&expected);
+	assert_eq!((width, height), (expected_width, expected_height), "{} changed size", name);
+	let different = actual.chunks(4).zip(expected.chunks(4)).filter(|(a, b)| a != b).count();
+	assert_eq!(different, 0, "{} differs from {} in {} pixels", name, path.display(), different);
+}
+
+#[test]
+fn starting_position() {
+	check("starting_position", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", RenderOptions::default());
+}
+
+#[test]
+fn black_in_check_from_black() {
+	check(
+		"black_in_check",
+		"r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4",
+		RenderOptions {
+			orientation: Color::Black,
+			last_move: Some(ChessMove::new(Square::H5, Square::F7, None)),
+			..RenderOptions::default()
+		}
+	);
+}
+
+#[test]
+fn other_theme_and_pieces() {
+	check(
+		"brown_dejavu",
+		"8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 99 50",
+		RenderOptions { theme: "brown".to_owned(), pieces: "dejavu".to_owned(), ..RenderOptions::default() }
+	);
+}
+
+#[test]
+fn arrows_and_marks() {
+	check(
+		"arrows_and_marks",
+		"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+		RenderOptions {
+			orientation: Color::Black,
+			arrows: ["f1b5", "f3e5red", "b8c6blue", "g1h3yellow"].iter().map(|text| parse_arrow(text).unwrap()).collect(),
+			marks: ["@e5red", "@d4"].iter().map(|text| parse_mark(text).unwrap()).collect(),
+			..RenderOptions::default()
+		}
+	);
+}
+
+#[test]
+fn side_panel() {
+	let game = "e4 d5 exd5 Qxd5 Nc3 Qa5 d4 Nf6 Nf3 Bf5 Bc4 e6 Bd2 c6 Nd5 Qd8 Nxf6+ gxf6 Bb4 Bxb4+ c3 Be7 Qb3 Qxd4 Qxb7 Qxc4 Qxa8 Qxc3+ Ke2";
+	let (position, moves) = play_line(&Board::default(), game).unwrap_or_else(|e| panic!("{}", e));
+	check(
+		"side_panel",
+		&position.to_string(),
+		RenderOptions {
+			orientation: Color::Black,
+			last_move: moves.last().cloned(),
+			panel: Some(Panel { names: ["Magnus the Magnificent Carlsen".to_owned(), "hikaru".to_owned()], moves }),
+			..RenderOptions::default()
+		}
+	);
+}
+
+#[test]
+fn small_squares() {
+	check(
+		"small_squares",
+		"r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4",
+		RenderOptions {
+			last_move: Some(ChessMove::new(Square::H5, Square::F7, None)),
+			arrows: vec![(Square::C4, Square::F7, Brush::Green)],
+			panel: Some(Panel { names: ["white".to_owned(), "black".to_owned()], moves: Vec::new() }),
+			square_size: 60,
+			..RenderOptions::default()
+		}
+	);
+}
+
+#[test]
+fn replay_shows_every_position() {
+	let (position, moves) = play_line(&Board::default(), "e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7#").unwrap_or_else(|e| panic!("{}", e));
+	let gif = renderer().render_replay(&moves, &RenderOptions::default(), 50);
+
+	let mut options = gif::DecodeOptions::new();
+	options.set_color_output(gif::ColorOutput::RGBA);
+	let mut decoder = options.read_info(&gif[..]).unwrap();
+	assert_eq!((decoder.width(), decoder.height()), (720, 720));
+	let mut delays = Vec::new();
+	let mut canvas = vec![0u8; 720 * 720 * 4];
+	while let Some(frame) = decoder.read_next_frame().unwrap() {
+		delays.push(frame.delay);
+		for row in 0..frame.height as usize {
+			let dst = ((frame.top as usize + row) * 720 + frame.left as usize) * 4;
+			let src = row * frame.width as usize * 4;
+			canvas[dst..dst + frame.width as usize * 4].copy_from_slice(&frame.buffer[src..src + frame.width as usize * 4]);
+		}
+	}
+	assert_eq!(delays.len(), moves.len() + 1);
+	assert!(delays[..moves.len()].iter().all(|&delay| delay == 50));
+	assert_eq!(delays[moves.len()], 200);
+
+	// The palette is approximate, so compare the assembled last frame with a still of the final position loosely
+	let last_move = RenderOptions { last_move: moves.last().cloned(), ..RenderOptions::default() };
+	let still = renderer().render(&position, &last_move);
+	let far = canvas.iter().zip(&still.bytes).filter(|(a, b)| (**a as i32 - **b as i32).abs() > 24).count();
+	assert!(far < canvas.len() / 100, "{} channels of the last frame are far from the still", far);
+}
+
+#[test]
+fn square_sizes() {
+	let renderer = renderer();
+	let position = Board::default();
+	for &(size, width) in &[(40, 360), (60, 540), (80, 720), (120, 1080), (100, 720)] {
+		let board = renderer.render(&position, &RenderOptions { square_size: size, ..RenderOptions::default() });
+		assert_eq!((board.width, board.height), (width, width), "board at {}px squares", size);
+	}
+	let panel = Some(Panel { names: ["a".to_owned(), "b".to_owned()], moves: Vec::new() });
+	let board = renderer.render(&position, &RenderOptions { square_size: 40, panel, ..RenderOptions::default() });
+	assert_eq!((board.width, board.height), (520, 360));
+}
+
+#[test]
+fn file_formats() {
+	let renderer = renderer();
+	let position = Board::default();
+	let file = |format| renderer.render_file(&position, &RenderOptions { format, ..RenderOptions::default() });
+
+	let palette = file(ImageFormat::PalettePng);
+	let mut decoder = png::Decoder::new(&palette[..]);
+	decoder.set_transformations(png::Transformations::IDENTITY);
+	let (info, _) = decoder.read_info().unwrap();
+	assert_eq!((info.width, info.height, info.color_type), (720, 720, png::ColorType::Indexed));
+	assert!(palette.len() < file(ImageFormat::Png).len());
+
+	assert!(file(ImageFormat::Jpeg).starts_with(&[0xFF, 0xD8, 0xFF]));
+	let webp = file(ImageFormat::Webp);
+	assert!(webp.starts_with(b"RIFF") && &webp[8..12] == b"WEBP");
+}
+
+#[test]
+fn annotation_syntax() {
+	assert!(parse_arrow("e2e4") == Some((Square::E2, Square::E4, Brush::Green)));
+	assert!(parse_arrow("a7a8blue") == Some((Square::A7, Square::A8, Brush::Blue)));
+	assert!(parse_mark("@h1yellow") == Some((Square::H1, Brush::Yellow)));
+	for text in ["e2e2", "e2e9", "e2e4purple", "e2", "KQkq", "é2e4"] {
+		assert!(parse_arrow(text).is_none(), "{} isn't an arrow", text);
+	}
+	for text in ["d5", "@d9", "@d5pink", "@"] {
+		assert!(parse_mark(text).is_none(), "{} isn't a mark", text);
+	}
+}
+
+#[test]
+fn unknown_names_fall_back_to_defaults() {
+	let renderer = renderer();
+	let position = Board::default();
+	let fallback = RenderOptions { theme: "no such theme".to_owned(), pieces: "no such set".to_owned(), ..RenderOptions::default() };
+	assert_eq!(renderer.render(&position, &fallback).bytes, renderer.render(&position, &RenderOptions::default()).bytes);
+}